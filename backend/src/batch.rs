@@ -0,0 +1,223 @@
+use std::{
+	collections::BTreeMap,
+	io::{Cursor, Read, Write},
+};
+
+use lopdf::{Document, Object, ObjectId};
+use scorecard_to_pdf::Return;
+use common::Schedule;
+use serde::Deserialize;
+use wca_oauth::WcifContainer;
+use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+use crate::wcif;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchFormat {
+	Pdf,
+	Zip,
+}
+
+/// Parses a round id such as `333-r1` into event id and round number.
+pub fn parse_round(round: &str) -> Option<(&str, usize)> {
+	let (event, round_no) = round.split_once("-r")?;
+	Some((event, round_no.parse().ok()?))
+}
+
+/// Groups a round the same way the group editor does before any manual changes, including moving competitors out of
+/// groups which clash with their other assignments and the grouping constraints. Returns `None` if nobody is in the
+/// round yet.
+pub fn auto_groups(
+	wcif: &mut WcifContainer,
+	event: &str,
+	round: usize,
+	stages: u64,
+	stations: u64,
+	seeded: bool,
+) -> Option<Vec<Vec<usize>>> {
+	let delegates = wcif.reg_ids_of_delegates();
+	let (competitors, _) =
+		wca_scorecards_lib::wcif::wca_live_get_competitors_for_round(wcif, event, round);
+	if competitors.is_empty() {
+		return None;
+	}
+	let rankings = seeded.then(|| wcif::rankings(wcif, event, round));
+	let mut groups = common::make_groups(
+		competitors.into_iter().map(|x| x as u64).collect(),
+		delegates.into_iter().map(|x| x as u64).collect(),
+		wcif::newcomers(wcif),
+		stages,
+		stations,
+		rankings.as_ref(),
+	);
+	let schedule = Schedule::new(wcif::round_window(wcif, event, round), wcif::busy_windows(wcif, event, round));
+	common::apply_schedule_and_constraints(&mut groups, &schedule, &wcif::constraints(wcif));
+	Some(
+		groups
+			.into_iter()
+			.map(|group| group.into_iter().map(|id| id as usize).collect())
+			.collect(),
+	)
+}
+
+/// Named pdf files of a generated round. Zip files, as generated when using one group per stage, are unpacked.
+pub fn pdf_files(name: &str, generated: Return) -> Vec<(String, Vec<u8>)> {
+	match generated {
+		Return::Pdf(pdf) => vec![(format!("{name}.pdf"), pdf)],
+		Return::Zip(zip) => {
+			let mut archive = ZipArchive::new(Cursor::new(zip)).expect("Generated zip is valid");
+			(0..archive.len())
+				.map(|idx| {
+					let mut file = archive.by_index(idx).expect("Index is in bounds");
+					let mut data = Vec::new();
+					file.read_to_end(&mut data).expect("Generated zip is valid");
+					(format!("{name}-{}", file.name()), data)
+				})
+				.collect()
+		}
+	}
+}
+
+pub fn zip_files(files: Vec<(String, Vec<u8>)>) -> Vec<u8> {
+	let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+	for (name, data) in files {
+		writer
+			.start_file(name, FileOptions::default())
+			.expect("Writing to memory does not fail");
+		writer
+			.write_all(&data)
+			.expect("Writing to memory does not fail");
+	}
+	writer
+		.finish()
+		.expect("Writing to memory does not fail")
+		.into_inner()
+}
+
+/// Concatenates the pages of several pdf files into one pdf.
+pub fn merge_pdfs(pdfs: Vec<Vec<u8>>) -> Vec<u8> {
+	let mut max_id = 1;
+	let mut pages = Vec::new();
+	let mut objects = BTreeMap::new();
+	for pdf in pdfs {
+		let mut document = Document::load_mem(&pdf).expect("Generated pdf is valid");
+		document.renumber_objects_with(max_id);
+		max_id = document.max_id + 1;
+		for (_, id) in document.get_pages() {
+			let page = document.get_object(id).expect("Page exists").to_owned();
+			pages.push((id, page));
+		}
+		objects.extend(document.objects);
+	}
+
+	let mut merged = Document::with_version("1.5");
+	let mut catalog: Option<(ObjectId, Object)> = None;
+	let mut pages_root: Option<(ObjectId, Object)> = None;
+	for (id, object) in objects {
+		match object.type_name().unwrap_or("") {
+			"Catalog" => {
+				let id = catalog.as_ref().map_or(id, |(id, _)| *id);
+				catalog = Some((id, object));
+			}
+			"Pages" => {
+				let mut dictionary = object.as_dict().expect("Pages is a dictionary").clone();
+				if let Some((_, Object::Dictionary(old))) = &pages_root {
+					dictionary.extend(old);
+				}
+				let id = pages_root.as_ref().map_or(id, |(id, _)| *id);
+				pages_root = Some((id, Object::Dictionary(dictionary)));
+			}
+			"Page" | "Outlines" | "Outline" => {}
+			_ => {
+				merged.objects.insert(id, object);
+			}
+		}
+	}
+	let (catalog_id, catalog) = catalog.expect("Pdf has a catalog");
+	let (pages_id, pages_root) = pages_root.expect("Pdf has pages");
+
+	for (id, page) in &pages {
+		let mut dictionary = page.as_dict().expect("Page is a dictionary").clone();
+		dictionary.set("Parent", pages_id);
+		merged.objects.insert(*id, Object::Dictionary(dictionary));
+	}
+	let mut dictionary = pages_root.as_dict().expect("Pages is a dictionary").clone();
+	dictionary.set("Count", pages.len() as u32);
+	dictionary.set(
+		"Kids",
+		pages
+			.into_iter()
+			.map(|(id, _)| Object::Reference(id))
+			.collect::<Vec<_>>(),
+	);
+	merged
+		.objects
+		.insert(pages_id, Object::Dictionary(dictionary));
+	let mut dictionary = catalog.as_dict().expect("Catalog is a dictionary").clone();
+	dictionary.set("Pages", pages_id);
+	dictionary.remove(b"Outlines");
+	merged
+		.objects
+		.insert(catalog_id, Object::Dictionary(dictionary));
+
+	merged.trailer.set("Root", catalog_id);
+	merged.max_id = merged.objects.len() as u32;
+	merged.renumber_objects();
+	merged.compress();
+	let mut data = Vec::new();
+	merged
+		.save_to(&mut data)
+		.expect("Writing to memory does not fail");
+	data
+}
+
+#[cfg(test)]
+mod tests {
+	use lopdf::dictionary;
+
+	use super::*;
+
+	fn pdf(pages: usize) -> Vec<u8> {
+		let mut document = Document::with_version("1.5");
+		let pages_id = document.new_object_id();
+		let kids: Vec<Object> = (0..pages)
+			.map(|_| {
+				let page = dictionary! {
+					"Type" => "Page",
+					"Parent" => pages_id,
+					"MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+				};
+				document.add_object(page).into()
+			})
+			.collect();
+		let pages_root = dictionary! {
+			"Type" => "Pages",
+			"Kids" => kids,
+			"Count" => pages as u32,
+		};
+		document.objects.insert(pages_id, Object::Dictionary(pages_root));
+		let catalog_id = document.add_object(dictionary! {
+			"Type" => "Catalog",
+			"Pages" => pages_id,
+		});
+		document.trailer.set("Root", catalog_id);
+		let mut data = Vec::new();
+		document.save_to(&mut data).unwrap();
+		data
+	}
+
+	#[test]
+	fn merged_pdf_has_the_pages_of_every_pdf() {
+		let merged = merge_pdfs(vec![pdf(2), pdf(1), pdf(3)]);
+		let document = Document::load_mem(&merged).unwrap();
+		assert_eq!(document.get_pages().len(), 6);
+	}
+
+	#[test]
+	fn round_ids_are_parsed() {
+		assert_eq!(parse_round("333oh-r2"), Some(("333oh", 2)));
+		assert_eq!(parse_round("333"), None);
+		assert_eq!(parse_round("333-rx"), None);
+	}
+}
@@ -1,88 +1,572 @@
-use std::{collections::HashMap, time::{Instant, Duration}};
+use std::{collections::HashMap, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, MutexGuard, PoisonError, RwLock}, time::{Duration, SystemTime, UNIX_EPOCH}};
 
+use ring::{constant_time::verify_slices_are_equal, rand::{SecureRandom, SystemRandom}};
 use wca_oauth::{OAuth, WcifContainer};
 
-use crate::Config;
+use crate::{error::Error, history::History, logging::wca_call, metrics, store::{SessionStore, StoredSession}, wcif, Config};
 
-pub(crate) struct DB {
-    config: Config,
-    sessions: HashMap<String, Session>
+/// All sessions. The map is only locked to look up, add or remove sessions and every session has its own locks,
+/// so requests of different users never wait on each other.
+pub struct DB {
+    /// Replaced when the config file is reloaded.
+    config: RwLock<Arc<Config>>,
+    sessions: RwLock<HashMap<String, Arc<Session>>>,
+    store: Option<SessionStore>,
+    history: History,
+    /// Whether the sessions changed since they were last written to the session store.
+    dirty: AtomicBool,
+    /// Wakes up `write_sessions` when the sessions changed.
+    changed: tokio::sync::Notify,
+    /// Held while writing so snapshots are written in the order they are taken.
+    save_lock: Mutex<()>,
 }
 
 impl DB {
-    pub fn new(config: Config) -> DB {
-        DB { config, sessions: HashMap::new() }
+    /// Creates the database and loads the sessions of the session store if one is configured.
+    pub fn new(config: Config) -> Result<DB, String> {
+        let store = match (&config.session_path, &config.session_key) {
+            (Some(path), Some(key)) => Some(SessionStore::new(path.clone(), key)?),
+            (Some(_), None) => return Err("session_key must be set when session_path is set".to_string()),
+            (None, _) => None,
+        };
+        let sessions: HashMap<_, _> = match store.as_ref().map(SessionStore::load) {
+            Some(Ok(stored)) => stored.into_iter()
+                .map(|(id, stored)| (id, Arc::new(Session::restore(stored))))
+                .collect(),
+            Some(Err(e)) => {
+                log::error!("Starting without stored sessions: {e}");
+                HashMap::new()
+            }
+            None => HashMap::new(),
+        };
+        log::info!("Loaded {} sessions", sessions.len());
+        let history = History::new(config.snapshot_dir.clone())?;
+        let db = DB {
+            config: RwLock::new(Arc::new(config)),
+            sessions: RwLock::new(sessions),
+            store,
+            history,
+            dirty: AtomicBool::new(false),
+            changed: tokio::sync::Notify::new(),
+            save_lock: Mutex::new(()),
+        };
+        db.clean();
+        Ok(db)
     }
 
-    pub fn config(&self) -> &Config {
-        &self.config
+    /// Marks the sessions as changed, so `write_sessions` writes them to the session store. Requests never wait for the
+    /// disk this way, and many changes in a row, such as the drafts of a busy group editor, are written once. Does
+    /// nothing if no store is configured.
+    pub fn save(&self) {
+        if self.store.is_none() {
+            return;
+        }
+        self.dirty.store(true, Ordering::Release);
+        self.changed.notify_one();
+    }
+
+    /// Writes the sessions to the session store whenever they changed. Runs until the server stops.
+    pub async fn write_sessions(self: Arc<Self>) {
+        loop {
+            self.changed.notified().await;
+            let db = self.clone();
+            // Serializing and encrypting every session takes a while, so it does not hold up a worker thread.
+            if let Err(e) = tokio::task::spawn_blocking(move || db.flush()).await {
+                log::error!("Could not save sessions: {e}");
+            }
+        }
+    }
+
+    /// Writes the sessions to the session store right away if they changed since they were last written.
+    pub fn flush(&self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let _guard = self.save_lock.lock().unwrap_or_else(PoisonError::into_inner);
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        let sessions: Vec<_> = self.sessions.read().unwrap()
+            .iter()
+            .map(|(id, session)| (id.clone(), session.clone()))
+            .collect();
+        let stored = sessions.into_iter()
+            .map(|(id, session)| (id, session.stored()))
+            .collect();
+        if let Err(e) = store.save(&stored) {
+            log::error!("Could not save sessions: {e}");
+        }
+    }
+
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    pub fn set_config(&self, config: Config) {
+        *self.config.write().unwrap() = Arc::new(config);
+    }
+
+    /// Snapshots of the wcifs taken before patching.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.config().session_timeout * 60)
+    }
+
+    /// Number of sessions which have not timed out. Timed out sessions are only removed every few minutes.
+    pub fn session_count(&self) -> usize {
+        let timeout = self.timeout();
+        self.sessions.read().unwrap().values().filter(|session| !session.expired(timeout)).count()
+    }
+
+    pub fn cached_wcif_count(&self) -> usize {
+        let sessions: Vec<_> = self.sessions.read().unwrap().values().cloned().collect();
+        sessions.iter().map(|session| session.state().wcif.len()).sum()
     }
 
     pub fn session_exists(&self, session: &str) -> bool {
-        self.sessions.contains_key(session)
+        self.sessions.read().unwrap()
+            .get(session)
+            .is_some_and(|session| !session.expired(self.timeout()))
+    }
+
+    /// Whether the session exists and does not need a new login to the WCA website.
+    pub fn logged_in(&self, session: &str) -> bool {
+        self.session_exists(session) && !self.sessions.read().unwrap()[session].needs_login()
     }
 
-    pub fn session_mut(&mut self, session: &str) -> Option<&mut Session> {
-        self.sessions.get_mut(session)
+    /// Looks up a session which has not timed out and marks it as active. The WCA client of the session is dropped
+    /// once its tokens run out, so the user is asked to log in again.
+    pub(crate) async fn session(&self, session: &str) -> Option<Arc<Session>> {
+        let session = self.sessions.read().unwrap().get(session).cloned()?;
+        if session.expired(self.timeout()) {
+            return None;
+        }
+        session.touch();
+        if session.login_remaining() == Some(Duration::ZERO) {
+            session.log_out_of_wca().await;
+        }
+        Some(session)
     }
 
-    pub async fn insert_session(&mut self, auth_code: String) {
-        let auth_code_clone = auth_code.clone();
-        let oauth = OAuth::get_auth(
-            self.config.client_id.clone(), 
-            self.config.client_secret.clone(), 
-            self.config.redirect_uri.clone(), 
-            auth_code)
+    /// Logs in with the OAuth authorization code and returns the id of the session. If the browser still has a
+    /// session which needs a new login, that session gets the login, so its drafts are kept.
+    pub async fn insert_session(&self, auth_code: String, previous: Option<&str>) -> String {
+        let config = self.config();
+        let oauth = wca_call("get_auth", OAuth::get_auth(
+            config.client_id.clone(),
+            config.client_secret.clone(),
+            config.redirect_uri.clone(),
+            auth_code))
             .await;
-        self.sessions.insert(auth_code_clone, Session::new(oauth));
+        if let Some(id) = previous.filter(|id| self.session_exists(id)) {
+            let session = self.sessions.read().unwrap()[id].clone();
+            if session.needs_login() {
+                session.log_in(oauth).await;
+                return id.to_owned();
+            }
+        }
+        let id = random_token();
+        let session = Arc::new(Session::new(Some(oauth)));
+        self.sessions.write().unwrap().insert(id.clone(), session);
+        self.save();
+        id
+    }
+
+    /// Takes the WCA client out of a session, which then needs a new login.
+    pub async fn take_oauth(&self, session: &str) -> Option<OAuth> {
+        let session = self.sessions.read().unwrap().get(session).cloned()?;
+        let oauth = session.oauth.lock().await.take();
+        session.state().logged_in = None;
+        oauth
+    }
+
+    /// Starts a session which works on a wcif from a file instead of the WCA website and returns its id. The WCA
+    /// client is only needed to generate scorecards and is never used to talk to the WCA website.
+    pub fn insert_offline_session(&self, competition: &str, wcif: WcifContainer, oauth: Option<OAuth>) -> String {
+        let id = random_token();
+        let session = Arc::new(Session::offline(competition, wcif, oauth));
+        self.sessions.write().unwrap().insert(id.clone(), session);
+        self.save();
+        id
     }
 
-    pub fn clean(&mut self) {
-        self.sessions.retain(|_, session| !session.expired());
+    pub fn remove_session(&self, session: &str) {
+        let removed = self.sessions.write().unwrap().remove(session);
+        if removed.is_some() {
+            self.save();
+        }
+    }
+
+    pub fn clean(&self) {
+        let mut sessions = self.sessions.write().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, session| !session.expired(self.timeout()));
+        let removed = sessions.len() != before;
+        drop(sessions);
+        if removed {
+            self.save();
+        }
     }
 }
 
+/// Competition id, event id and round number of a round.
+pub(crate) type RoundKey = (String, String, u64);
+
+/// WCA access tokens are valid for two hours. The WCA client is dropped a bit earlier, so no request runs into the
+/// end of its tokens halfway.
+const LOGIN_LIFETIME: Duration = Duration::from_secs(110 * 60);
+
+/// A logged in user. The OAuth client is locked while talking to the WCA website, the rest of the session is only
+/// locked for short synchronous work and never across an await.
 pub(crate) struct Session {
-    oauth: OAuth,
+    /// None if the user has to log in to the WCA website again, as sessions restored after a restart do. The WCA
+    /// client can not be rebuilt from its tokens.
+    oauth: tokio::sync::Mutex<Option<OAuth>>,
+    state: Mutex<SessionState>,
+    /// Offline sessions never talk to the WCA website. Their wcifs come from files and all changes stay in the cache.
+    offline: bool,
+}
+
+struct SessionState {
     wcif: HashMap<String, WcifContainer>,
-    created: Instant,
+    /// The wcifs as the WCA website had them when the user last opened the group editor or patched, to find out what
+    /// others changed there in the meantime.
+    downloaded: HashMap<String, WcifContainer>,
+    drafts: HashMap<RoundKey, Vec<Vec<u64>>>,
+    /// Latest revision the editor saved or discarded the draft of a round with, so saves arriving out of order do not
+    /// overwrite newer ones. Not stored, as the editor continues from the revision it was opened with.
+    draft_revisions: HashMap<RoundKey, u64>,
+    /// When the session got its WCA client, None if it has none. Kept here so it can be checked without waiting for
+    /// the client's lock.
+    logged_in: Option<SystemTime>,
+    last_active: SystemTime,
+    csrf_token: String,
+}
+
+impl SessionState {
+    /// Remembers the revision if it is newer than the last one of the round.
+    fn newer_draft_revision(&mut self, round: &RoundKey, revision: u64) -> bool {
+        let last = self.draft_revisions.entry(round.clone()).or_default();
+        if revision <= *last {
+            return false;
+        }
+        *last = revision;
+        true
+    }
 }
 
 impl Session {
-    fn new(oauth: OAuth) -> Session {
-        Session { oauth, wcif: HashMap::new(), created: Instant::now() }   
+    fn new(oauth: Option<OAuth>) -> Session {
+        let state = SessionState {
+            wcif: HashMap::new(),
+            downloaded: HashMap::new(),
+            drafts: HashMap::new(),
+            draft_revisions: HashMap::new(),
+            logged_in: oauth.is_some().then(SystemTime::now),
+            last_active: SystemTime::now(),
+            csrf_token: random_token(),
+        };
+        Session { oauth: tokio::sync::Mutex::new(oauth), state: Mutex::new(state), offline: false }
     }
 
-    pub fn oauth_mut(&mut self) -> &mut OAuth {
-        &mut self.oauth
+    fn offline(competition: &str, wcif: WcifContainer, oauth: Option<OAuth>) -> Session {
+        let mut session = Session::new(oauth);
+        session.offline = true;
+        session.insert_wcif(competition, wcif);
+        session
     }
 
-    pub async fn wcif_force_download(&mut self, competition: &str) {
-            self.wcif.insert(competition.to_owned(), self.oauth.get_wcif(competition).await.unwrap());
+    fn restore(stored: StoredSession) -> Session {
+        let wcifs = stored.wcifs.into_iter()
+            .filter_map(|(competition, json)| match wcif::from_value(json) {
+                Ok(wcif) => Some((competition, wcif)),
+                Err(e) => {
+                    log::error!("Dropping stored wcif of {competition}: {e}");
+                    None
+                }
+            })
+            .collect();
+        let state = SessionState {
+            wcif: wcifs,
+            downloaded: HashMap::new(),
+            drafts: stored.drafts.into_iter().collect(),
+            draft_revisions: HashMap::new(),
+            logged_in: None,
+            last_active: UNIX_EPOCH + Duration::from_secs(stored.last_active),
+            csrf_token: stored.csrf_token,
+        };
+        Session { oauth: tokio::sync::Mutex::new(None), state: Mutex::new(state), offline: stored.offline }
     }
 
-    pub async fn wcif_mut(&mut self, competition: &str) -> &mut WcifContainer {
-        if !self.wcif.contains_key(competition) {
-            self.wcif.insert(competition.to_owned(), self.oauth.get_wcif(competition).await.unwrap());
+    fn stored(&self) -> StoredSession {
+        let state = self.state();
+        StoredSession {
+            last_active: unix_seconds(state.last_active),
+            csrf_token: state.csrf_token.clone(),
+            drafts: state.drafts.iter().map(|(round, groups)| (round.clone(), groups.clone())).collect(),
+            offline: self.offline,
+            wcifs: if self.offline {
+                state.wcif.iter().map(|(competition, wcif)| (competition.clone(), wcif::to_json(wcif))).collect()
+            } else {
+                Vec::new()
+            },
         }
-        // key competition is always occupied due to if above.
-        self.wcif.get_mut(competition).unwrap()
     }
 
-    pub async fn remove_wcif(&mut self, competition: &str) -> WcifContainer {
-       if self.wcif.contains_key(competition) {
-            self.wcif.remove(competition).unwrap()
-       }
-       else {
-            self.oauth.get_wcif(competition).await.unwrap()
-       }
+    pub fn is_offline(&self) -> bool {
+        self.offline
     }
 
-    pub fn insert_wcif(&mut self, competition: &str, wcif: WcifContainer) {
-        self.wcif.insert(competition.to_string(), wcif);
+    /// Id and name of every competition with a cached wcif, which for offline sessions are all competitions.
+    pub fn cached_competitions(&self) -> Vec<(String, String)> {
+        let mut competitions: Vec<_> = self.state().wcif.iter()
+            .map(|(id, wcif)| (id.clone(), wcif::name(wcif)))
+            .collect();
+        competitions.sort();
+        competitions
     }
 
-    fn expired(&self) -> bool {
-        self.created.elapsed() > Duration::from_secs(3600)
+    /// The state only holds caches and drafts, so it is still usable after a handler panicked while holding it.
+    fn state(&self) -> MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
     }
+
+    /// Locks the OAuth client of the session. Other WCA calls of the same session wait until the guard is dropped.
+    /// Fails if the user has to log in again first, or for offline sessions which were started without a login.
+    pub async fn oauth(&self) -> Result<tokio::sync::MappedMutexGuard<'_, OAuth>, Error> {
+        tokio::sync::MutexGuard::try_map(self.oauth.lock().await, Option::as_mut).map_err(|_| self.no_oauth())
+    }
+
+    /// Takes the OAuth client out of the session, for the wcif to own it while it is patched or scorecards are
+    /// generated. Other WCA calls of the same session wait until it is given back.
+    pub async fn lend_oauth(&self) -> Result<(LentOAuth<'_>, OAuth), Error> {
+        let mut guard = self.oauth.lock().await;
+        let oauth = guard.take().ok_or_else(|| self.no_oauth())?;
+        Ok((LentOAuth { session: self, guard }, oauth))
+    }
+
+    fn no_oauth(&self) -> Error {
+        if self.offline {
+            Error::BadRequest("Scorecards can only be generated offline if you were logged in to the WCA website \
+                when you started working offline.".to_string())
+        } else {
+            Error::LoginRequired
+        }
+    }
+
+    fn needs_login(&self) -> bool {
+        !self.offline && self.state().logged_in.is_none()
+    }
+
+    async fn log_in(&self, oauth: OAuth) {
+        *self.oauth.lock().await = Some(oauth);
+        self.state().logged_in = Some(SystemTime::now());
+    }
+
+    async fn log_out_of_wca(&self) {
+        *self.oauth.lock().await = None;
+        self.state().logged_in = None;
+    }
+
+    /// How long the WCA login of the session lasts. None for offline sessions and sessions without a login.
+    fn login_remaining(&self) -> Option<Duration> {
+        if self.offline {
+            return None;
+        }
+        let elapsed = self.state().logged_in?.elapsed().unwrap_or_default();
+        Some(LOGIN_LIFETIME.saturating_sub(elapsed))
+    }
+
+    async fn download_wcif(&self, competition: &str) -> Result<WcifContainer, Error> {
+        if self.offline {
+            return Err(Error::CompetitionNotFound(competition.to_owned()));
+        }
+        let mut oauth = self.oauth().await?;
+        match wca_call("get_wcif", oauth.get_wcif(competition)).await {
+            Ok(wcif) => Ok(wcif),
+            Err(e) => {
+                // Tell apart a competition the user cannot access from the WCA website being unavailable. The client
+                // gives no error for the list of competitions, so an empty list may just as well be a failed call.
+                let managed = wca_call("get_competitions_managed_by_me", oauth.get_competitions_managed_by_me()).await;
+                if managed.is_empty() || managed.iter().any(|c| c.id() == competition) {
+                    Err(Error::Wca(format!("{e:?}")))
+                } else {
+                    Err(Error::CompetitionNotFound(competition.to_owned()))
+                }
+            }
+        }
+    }
+
+    /// Downloads the wcif again. Offline sessions keep the wcif they have.
+    pub async fn wcif_force_download(&self, competition: &str) -> Result<(), Error> {
+        if self.offline {
+            if !self.state().wcif.contains_key(competition) {
+                return Err(Error::CompetitionNotFound(competition.to_owned()));
+            }
+            return Ok(());
+        }
+        let wcif = self.download_wcif(competition).await?;
+        self.insert_wcif(competition, wcif);
+        Ok(())
+    }
+
+    /// Downloads the wcif again before the groups of the rounds are patched, so changes made on the WCA website since
+    /// the group editor was opened are kept. Fails if someone else changed the groups of these rounds in the meantime,
+    /// after caching the current wcif so the user can look at the new groups by opening the editor again.
+    pub async fn refresh_for_patch(&self, competition: &str, rounds: &[String]) -> Result<(), Error> {
+        if self.offline {
+            return Ok(());
+        }
+        let base = self.state().downloaded.get(competition).map(wcif::copy);
+        let wcif = self.download_wcif(competition).await?;
+        let conflicts = base.map(|base| wcif::round_diff(&base, &wcif, rounds));
+        self.insert_wcif(competition, wcif);
+        match conflicts {
+            Some(conflicts) if !conflicts.is_empty() => Err(Error::Conflict(rounds.join(", "), conflicts.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remembers the cached wcif as the version on the WCA website the user works on, after it was shown in the group
+    /// editor or patched.
+    pub fn set_base(&self, competition: &str) {
+        if self.offline {
+            return;
+        }
+        let mut state = self.state();
+        if let Some(wcif) = state.wcif.get(competition) {
+            let wcif = wcif::copy(wcif);
+            state.downloaded.insert(competition.to_owned(), wcif);
+        }
+    }
+
+    /// Runs `f` on the cached wcif of the competition, downloading it first if it is not cached.
+    pub async fn with_wcif<T>(&self, competition: &str, f: impl FnOnce(&mut WcifContainer) -> T) -> Result<T, Error> {
+        let mut wcif = self.remove_wcif(competition).await?;
+        let result = f(&mut wcif);
+        self.insert_wcif(competition, wcif);
+        Ok(result)
+    }
+
+    pub async fn remove_wcif(&self, competition: &str) -> Result<WcifContainer, Error> {
+        let cached = self.state().wcif.remove(competition);
+        match cached {
+            Some(wcif) => Ok(wcif),
+            None => self.download_wcif(competition).await,
+        }
+    }
+
+    pub fn insert_wcif(&self, competition: &str, wcif: WcifContainer) {
+        self.state().wcif.insert(competition.to_string(), wcif);
+    }
+
+    /// Uploads the cached wcif of the competition to the WCA website. Does nothing offline, where the cached wcif is
+    /// all there is.
+    pub async fn patch_wcif(&self, competition: &str) -> Result<(), Error> {
+        if self.offline {
+            return Ok(());
+        }
+        let wcif = self.remove_wcif(competition).await?;
+        let (lent, oauth) = match self.lend_oauth().await {
+            Ok(lent) => lent,
+            Err(e) => {
+                self.insert_wcif(competition, wcif);
+                return Err(e);
+            }
+        };
+        let wcif_oauth = wcif.add_oauth(oauth);
+        let result = wca_call("patch", wcif_oauth.patch()).await;
+        metrics::WCIF_PATCHES.fetch_add(1, Ordering::Relaxed);
+        let (wcif, oauth) = wcif_oauth.disassemble();
+        lent.give_back(oauth);
+        self.insert_wcif(competition, wcif);
+        result.map_err(|e| Error::Wca(format!("{e:?}")))?;
+        self.set_base(competition);
+        Ok(())
+    }
+
+    pub fn draft(&self, round: &RoundKey) -> Option<Vec<Vec<u64>>> {
+        self.state().drafts.get(round).cloned()
+    }
+
+    pub fn draft_revision(&self, round: &RoundKey) -> u64 {
+        self.state().draft_revisions.get(round).copied().unwrap_or(0)
+    }
+
+    /// Saves the groups of the editor, unless a later revision was saved or discarded already. Returns whether the
+    /// draft was saved.
+    pub fn set_draft(&self, round: RoundKey, revision: u64, groups: Vec<Vec<u64>>) -> bool {
+        let mut state = self.state();
+        if !state.newer_draft_revision(&round, revision) {
+            return false;
+        }
+        state.drafts.insert(round, groups);
+        true
+    }
+
+    /// Discards the draft of the editor, unless a later revision was saved already.
+    pub fn discard_draft(&self, round: RoundKey, revision: u64) -> bool {
+        let mut state = self.state();
+        if !state.newer_draft_revision(&round, revision) {
+            return false;
+        }
+        state.drafts.remove(&round);
+        true
+    }
+
+    pub fn remove_draft(&self, round: &RoundKey) {
+        self.state().drafts.remove(round);
+    }
+
+    /// Token which state changing requests must include, so other sites can not make them on behalf of the user.
+    pub fn csrf_token(&self) -> String {
+        self.state().csrf_token.clone()
+    }
+
+    pub fn check_csrf(&self, token: &str) -> Result<(), Error> {
+        verify_slices_are_equal(self.state().csrf_token.as_bytes(), token.as_bytes()).map_err(|_| Error::Csrf)
+    }
+
+    fn touch(&self) {
+        self.state().last_active = SystemTime::now();
+    }
+
+    fn expired(&self, timeout: Duration) -> bool {
+        self.state().last_active.elapsed().is_ok_and(|elapsed| elapsed > timeout)
+    }
+}
+
+/// Holds the client lock of a session whose OAuth client was taken out by `Session::lend_oauth`. If the client is not
+/// given back, for example because generating the scorecards panicked, the user has to log in again.
+pub struct LentOAuth<'a> {
+    session: &'a Session,
+    guard: tokio::sync::MutexGuard<'a, Option<OAuth>>,
+}
+
+impl LentOAuth<'_> {
+    pub fn give_back(mut self, oauth: OAuth) {
+        *self.guard = Some(oauth);
+    }
+}
+
+impl Drop for LentOAuth<'_> {
+    fn drop(&mut self) {
+        if self.guard.is_none() {
+            self.session.state().logged_in = None;
+        }
+    }
+}
+
+/// Random hex encoded token which can not be guessed.
+pub fn random_token() -> String {
+    let mut bytes = [0; 32];
+    SystemRandom::new().fill(&mut bytes).expect("System random number generator is available");
+    hex::encode(bytes)
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
 }
@@ -0,0 +1,125 @@
+use std::fmt::{self, Display};
+
+use actix_web::{
+	body::BoxBody,
+	cookie::Cookie,
+	dev::ServiceResponse,
+	http::{
+		header::{HeaderValue, ACCEPT, CONTENT_TYPE, LOCATION},
+		Method, StatusCode,
+	},
+	HttpRequest, HttpResponse, ResponseError,
+};
+
+use crate::html;
+
+/// Errors returned by handlers. Each variant is rendered as an html error page with a matching status code.
+#[derive(Debug)]
+pub enum Error {
+	/// The request has no session cookie.
+	Unauthenticated,
+	/// The session cookie refers to a session which no longer exists.
+	SessionExpired,
+	/// The session needs a new login to the WCA website, which keeps the session.
+	LoginRequired,
+	CompetitionNotFound(String),
+	RoundNotFound(String),
+	SnapshotNotFound(u64),
+	/// The groups of the rounds were changed on the WCA website since the wcif was downloaded. Holds the rounds and
+	/// the conflicting changes.
+	Conflict(String, String),
+	/// A request to the WCA website failed.
+	Wca(String),
+	BadRequest(String),
+	/// A state changing request without the csrf token of the session.
+	Csrf,
+	/// The monitoring endpoints were requested by someone not allowed in the config.
+	Forbidden,
+	/// A handler panicked.
+	Internal(String),
+}
+
+impl Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Error::Unauthenticated => write!(f, "You are not logged in."),
+			Error::SessionExpired => write!(f, "Your session has expired. Please log in again."),
+			Error::LoginRequired => write!(f, "Please log in to the WCA website again."),
+			Error::CompetitionNotFound(id) => write!(
+				f,
+				"The competition {id} does not exist or you are not allowed to manage it."
+			),
+			Error::RoundNotFound(id) => write!(f, "The round {id} does not exist."),
+			Error::SnapshotNotFound(id) => write!(f, "The snapshot {id} does not exist."),
+			Error::Conflict(rounds, changes) => write!(
+				f,
+				"The groups of {rounds} were changed on the WCA website since you loaded them, so they were not \
+				patched. Changes made there: {changes}. Reload the round to see the current groups and try again."
+			),
+			Error::Wca(message) => write!(f, "The WCA website could not be reached: {message}"),
+			Error::BadRequest(message) => write!(f, "Bad request: {message}"),
+			Error::Csrf => write!(f, "The request could not be verified. Reload the page and try again."),
+			Error::Forbidden => write!(f, "You are not allowed to see this page."),
+			Error::Internal(message) => write!(f, "Internal server error: {message}"),
+		}
+	}
+}
+
+impl ResponseError for Error {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Error::Unauthenticated => StatusCode::UNAUTHORIZED,
+			Error::SessionExpired | Error::LoginRequired => StatusCode::SEE_OTHER,
+			Error::CompetitionNotFound(_) | Error::RoundNotFound(_) | Error::SnapshotNotFound(_) => StatusCode::NOT_FOUND,
+			Error::Conflict(..) => StatusCode::CONFLICT,
+			Error::Wca(_) => StatusCode::BAD_GATEWAY,
+			Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+			Error::Csrf | Error::Forbidden => StatusCode::FORBIDDEN,
+			Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+		}
+	}
+
+	fn error_response(&self) -> HttpResponse<BoxBody> {
+		let status = self.status_code();
+		if let Error::SessionExpired = self {
+			// Log in again and throw away the stale cookie.
+			let mut cookie = Cookie::named("scorecards");
+			cookie.make_removal();
+			return HttpResponse::build(status)
+				.insert_header((LOCATION, "/"))
+				.cookie(cookie)
+				.finish();
+		}
+		if let Error::LoginRequired = self {
+			return HttpResponse::build(status).insert_header((LOCATION, "/")).finish();
+		}
+		HttpResponse::build(status)
+			.content_type("html")
+			.body(html::error(status, &self.to_string()))
+	}
+}
+
+/// Whether the request loads a page in the browser, as opposed to a script fetching or sending data.
+fn is_page_load(request: &HttpRequest) -> bool {
+	let accepts_html = request
+		.headers()
+		.get(ACCEPT)
+		.and_then(|accept| accept.to_str().ok())
+		.is_some_and(|accept| accept.contains("text/html"));
+	request.method() == Method::GET && accepts_html
+}
+
+/// Page loads without a usable session are redirected to the login. Requests of scripts get a 401 with the message
+/// instead, as `fetch` follows redirects and would take the login page for a successful response.
+pub fn unauthorized_for_scripts(response: ServiceResponse) -> ServiceResponse {
+	let message = match response.response().error().and_then(|e| e.as_error::<Error>()) {
+		Some(error @ (Error::SessionExpired | Error::LoginRequired)) if !is_page_load(response.request()) => error.to_string(),
+		_ => return response,
+	};
+	response.map_body(|head, _| {
+		head.status = StatusCode::UNAUTHORIZED;
+		head.headers_mut().remove(LOCATION);
+		head.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("text/plain; charset=utf-8"));
+		BoxBody::new(message)
+	})
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+use wca_oauth::WcifContainer;
+
+use crate::{batch, html::escape, wcif};
+
+const HEADER: [&str; 6] = ["Round", "Group", "Station", "Registrant ID", "Name", "WCA ID"];
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+	Csv,
+	Xlsx,
+}
+
+impl ExportFormat {
+	pub fn content_type(self) -> &'static str {
+		match self {
+			ExportFormat::Csv => "text/csv; charset=utf-8",
+			ExportFormat::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+		}
+	}
+
+	pub fn extension(self) -> &'static str {
+		match self {
+			ExportFormat::Csv => "csv",
+			ExportFormat::Xlsx => "xlsx",
+		}
+	}
+
+	pub fn write(self, rows: &[Row]) -> Vec<u8> {
+		match self {
+			ExportFormat::Csv => csv(rows),
+			ExportFormat::Xlsx => xlsx(rows),
+		}
+	}
+}
+
+/// Group and station of a competitor in a round.
+pub struct Row {
+	/// Round id such as `333-r1`.
+	pub round: String,
+	pub group: u64,
+	pub station: Option<u64>,
+	pub registrant_id: u64,
+	pub name: String,
+	pub wca_id: Option<String>,
+}
+
+/// Name and WCA ID of every person by registrant id.
+fn persons(json: &Value) -> HashMap<u64, (String, Option<String>)> {
+	json.get("persons")
+		.and_then(Value::as_array)
+		.into_iter()
+		.flatten()
+		.filter_map(|person| {
+			let id = person.get("registrantId")?.as_u64()?;
+			let name = person.get("name").and_then(Value::as_str).unwrap_or_default().to_owned();
+			let wca_id = person.get("wcaId").and_then(Value::as_str).map(str::to_owned);
+			Some((id, (name, wca_id)))
+		})
+		.collect()
+}
+
+/// Every competitor assignment to a group in the wcif, in the order of the events and rounds of the competition.
+pub fn wcif_rows(wcif: &WcifContainer) -> Vec<Row> {
+	let json = wcif::to_json(wcif);
+	let persons = persons(&json);
+	let groups: HashMap<u64, (String, u64)> = wcif::group_activities(wcif).into_iter().collect();
+	let mut rows: Vec<Row> = json.get("persons")
+		.and_then(Value::as_array)
+		.into_iter()
+		.flatten()
+		.flat_map(|person| {
+			let id = person.get("registrantId").and_then(Value::as_u64);
+			let assignments = person.get("assignments").and_then(Value::as_array).into_iter().flatten();
+			assignments.filter_map(move |assignment| Some((id?, assignment)))
+		})
+		.filter(|(_, assignment)| assignment.get("assignmentCode").and_then(Value::as_str) == Some("competitor"))
+		.filter_map(|(id, assignment)| {
+			let (round, group) = groups.get(&assignment.get("activityId")?.as_u64()?)?.clone();
+			let (name, wca_id) = persons.get(&id).cloned().unwrap_or_default();
+			let station = assignment.get("stationNumber").and_then(Value::as_u64);
+			Some(Row { round, group, station, registrant_id: id, name, wca_id })
+		})
+		.collect();
+	let rounds: Vec<String> = json.get("events")
+		.and_then(Value::as_array)
+		.into_iter()
+		.flatten()
+		.flat_map(|event| event.get("rounds").and_then(Value::as_array).into_iter().flatten())
+		.filter_map(|round| Some(round.get("id")?.as_str()?.to_owned()))
+		.collect();
+	let position = |round: &str| rounds.iter().position(|other| other == round).unwrap_or(rounds.len());
+	rows.sort_by(|a, b| {
+		(position(&a.round), a.group, a.station, &a.name).cmp(&(position(&b.round), b.group, b.station, &b.name))
+	});
+	rows
+}
+
+/// The groups of a round as made in the group editor. Stations are numbered in the order of the group, like when the
+/// groups are patched.
+pub fn round_rows(wcif: &WcifContainer, event: &str, round: u64, groups: &[Vec<u64>]) -> Vec<Row> {
+	let persons = persons(&wcif::to_json(wcif));
+	groups.iter()
+		.zip(1..)
+		.flat_map(|(members, group)| members.iter().zip(1..).map(move |(&id, station)| (group, station, id)))
+		.map(|(group, station, id)| {
+			let (name, wca_id) = persons.get(&id).cloned().unwrap_or_default();
+			Row { round: format!("{event}-r{round}"), group, station: Some(station), registrant_id: id, name, wca_id }
+		})
+		.collect()
+}
+
+/// Quotes a field if needed. Fields which start like a formula get a `'` in front, so spreadsheet programs show names
+/// like `=1+1` from the registrations instead of evaluating them.
+fn csv_field(field: &str) -> String {
+	let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+		format!("'{field}")
+	} else {
+		field.to_owned()
+	};
+	if field.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field
+	}
+}
+
+fn csv(rows: &[Row]) -> Vec<u8> {
+	// The byte order mark makes spreadsheet programs read the names as utf-8.
+	let mut csv = String::from("\u{feff}");
+	csv.push_str(&HEADER.join(","));
+	csv.push_str("\r\n");
+	for row in rows {
+		let fields = [
+			csv_field(&row.round),
+			row.group.to_string(),
+			row.station.map_or_else(String::new, |station| station.to_string()),
+			row.registrant_id.to_string(),
+			csv_field(&row.name),
+			csv_field(row.wca_id.as_deref().unwrap_or_default()),
+		];
+		csv.push_str(&fields.join(","));
+		csv.push_str("\r\n");
+	}
+	csv.into_bytes()
+}
+
+enum Cell<'a> {
+	Text(&'a str),
+	Number(u64),
+	Empty,
+}
+
+fn xlsx_row(number: usize, cells: &[Cell]) -> String {
+	let cells: String = cells.iter()
+		.zip('A'..)
+		.map(|(cell, column)| match cell {
+			Cell::Text(text) => format!("<c r=\"{column}{number}\" t=\"inlineStr\"><is><t>{}</t></is></c>", escape(text)),
+			Cell::Number(value) => format!("<c r=\"{column}{number}\"><v>{value}</v></c>"),
+			Cell::Empty => String::new(),
+		})
+		.collect();
+	format!("<row r=\"{number}\">{cells}</row>")
+}
+
+/// A workbook with one sheet. Cells hold their text inline, so no shared strings or styles are needed.
+fn xlsx(rows: &[Row]) -> Vec<u8> {
+	let header = HEADER.map(Cell::Text);
+	let mut sheet_rows = vec![xlsx_row(1, &header)];
+	for (row, number) in rows.iter().zip(2..) {
+		sheet_rows.push(xlsx_row(number, &[
+			Cell::Text(&row.round),
+			Cell::Number(row.group),
+			row.station.map_or(Cell::Empty, Cell::Number),
+			Cell::Number(row.registrant_id),
+			Cell::Text(&row.name),
+			row.wca_id.as_deref().map_or(Cell::Empty, Cell::Text),
+		]));
+	}
+	let sheet = format!(
+		"<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+		<worksheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\"><sheetData>{}</sheetData></worksheet>",
+		sheet_rows.concat()
+	);
+	let files = [
+		("[Content_Types].xml", CONTENT_TYPES.to_owned()),
+		("_rels/.rels", ROOT_RELATIONSHIPS.to_owned()),
+		("xl/workbook.xml", WORKBOOK.to_owned()),
+		("xl/_rels/workbook.xml.rels", WORKBOOK_RELATIONSHIPS.to_owned()),
+		("xl/worksheets/sheet1.xml", sheet),
+	];
+	batch::zip_files(files.into_iter().map(|(name, data)| (name.to_owned(), data.into_bytes())).collect())
+}
+
+const CONTENT_TYPES: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+	<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+	<Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+	<Default Extension=\"xml\" ContentType=\"application/xml\"/>\
+	<Override PartName=\"/xl/workbook.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml\"/>\
+	<Override PartName=\"/xl/worksheets/sheet1.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>\
+	</Types>";
+
+const ROOT_RELATIONSHIPS: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+	<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+	<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" Target=\"xl/workbook.xml\"/>\
+	</Relationships>";
+
+const WORKBOOK: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+	<workbook xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\" \
+	xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\">\
+	<sheets><sheet name=\"Groups\" sheetId=\"1\" r:id=\"rId1\"/></sheets></workbook>";
+
+const WORKBOOK_RELATIONSHIPS: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+	<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+	<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet\" Target=\"worksheets/sheet1.xml\"/>\
+	</Relationships>";
@@ -0,0 +1,122 @@
+use std::{
+	collections::HashMap,
+	fs::{create_dir_all, read_dir, read_to_string, write},
+	sync::Mutex,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use wca_oauth::WcifContainer;
+
+use crate::wcif;
+
+/// Snapshots kept per competition when they are only kept in memory.
+const MAX_SNAPSHOTS_IN_MEMORY: usize = 50;
+
+/// Copy of a wcif taken before it was patched.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+	/// Milliseconds since the unix epoch, which also identifies the snapshot.
+	pub id: u64,
+	pub description: String,
+	pub wcif: Value,
+}
+
+impl Snapshot {
+	pub fn time(&self) -> String {
+		time(self.id)
+	}
+}
+
+/// When the snapshot with the id was taken, for people.
+pub fn time(id: u64) -> String {
+	Utc.timestamp_millis_opt(id as i64)
+		.single()
+		.map_or_else(String::new, |time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+}
+
+/// Snapshots of the wcifs of every competition, so a wrong patch can be rolled back. Snapshots are written to
+/// `<dir>/<competition id>/<snapshot id>.json` if a directory is configured and only kept in memory otherwise.
+pub struct History {
+	dir: Option<String>,
+	memory: Mutex<HashMap<String, Vec<Snapshot>>>,
+}
+
+impl History {
+	pub fn new(dir: Option<String>) -> Result<History, String> {
+		if let Some(dir) = &dir {
+			create_dir_all(dir).map_err(|e| format!("Could not create snapshot_dir {dir}: {e}"))?;
+		}
+		Ok(History { dir, memory: Mutex::new(HashMap::new()) })
+	}
+
+	/// Saves a snapshot of the wcif. Failing to save is logged, as it should not stop the patch.
+	pub fn save(&self, competition: &str, description: String, wcif: &WcifContainer) {
+		if !wcif::valid_competition_id(competition) {
+			return;
+		}
+		let id = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+		let snapshot = Snapshot { id, description, wcif: wcif::to_json(wcif) };
+		let Some(dir) = &self.dir else {
+			let mut memory = self.memory.lock().unwrap();
+			let snapshots = memory.entry(competition.to_owned()).or_default();
+			snapshots.push(snapshot);
+			if snapshots.len() > MAX_SNAPSHOTS_IN_MEMORY {
+				snapshots.remove(0);
+			}
+			return;
+		};
+		let dir = format!("{dir}/{competition}");
+		let saved = create_dir_all(&dir)
+			.map_err(|e| e.to_string())
+			.and_then(|_| serde_json::to_vec(&snapshot).map_err(|e| e.to_string()))
+			.and_then(|data| write(format!("{dir}/{id}.json"), data).map_err(|e| e.to_string()));
+		if let Err(e) = saved {
+			log::error!("Could not save snapshot of {competition}: {e}");
+		}
+	}
+
+	/// Id and description of every snapshot of the competition, newest first.
+	pub fn list(&self, competition: &str) -> Vec<(u64, String)> {
+		let mut snapshots: Vec<(u64, String)> = match &self.dir {
+			None => self.memory.lock().unwrap()
+				.get(competition)
+				.into_iter()
+				.flatten()
+				.map(|snapshot| (snapshot.id, snapshot.description.clone()))
+				.collect(),
+			Some(_) if !wcif::valid_competition_id(competition) => Vec::new(),
+			Some(dir) => read_dir(format!("{dir}/{competition}"))
+				.into_iter()
+				.flatten()
+				.filter_map(|entry| {
+					let id = entry.ok()?.path().file_stem()?.to_str()?.parse().ok()?;
+					Some((id, self.get(competition, id)?.description))
+				})
+				.collect(),
+		};
+		snapshots.sort_by(|(a, _), (b, _)| b.cmp(a));
+		snapshots
+	}
+
+	pub fn get(&self, competition: &str, id: u64) -> Option<Snapshot> {
+		match &self.dir {
+			None => self.memory.lock().unwrap()
+				.get(competition)?
+				.iter()
+				.find(|snapshot| snapshot.id == id)
+				.cloned(),
+			Some(dir) => {
+				if !wcif::valid_competition_id(competition) {
+					return None;
+				}
+				let data = read_to_string(format!("{dir}/{competition}/{id}.json")).ok()?;
+				serde_json::from_str(&data)
+					.map_err(|e| log::error!("Snapshot {id} of {competition} is not valid: {e}"))
+					.ok()
+			}
+		}
+	}
+}
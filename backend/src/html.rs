@@ -1,18 +1,29 @@
-use common::{RoundInfo,Competitors, to_base_64};
-use wca_oauth::Competition;
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use common::{RoundInfo,Competitors, Constraints, to_base_64};
+
+use crate::wcif::{Change, WcifDiff};
 
 const VALIDATED: &str = include_str!("../../frontend/html_src/validated.html");
 const ROUNDS: &str = include_str!("../../frontend/html_src/competition_rounds.html");
 const GROUP: &str = include_str!("../../frontend/html_src/group.html");
+const CONSTRAINTS: &str = include_str!("../../frontend/html_src/constraints.html");
+const ERROR: &str = include_str!("../../frontend/html_src/error.html");
+const LOGGED_OUT: &str = include_str!("../../frontend/html_src/logged_out.html");
+const OFFLINE: &str = include_str!("../../frontend/html_src/offline.html");
+const PREVIEW: &str = include_str!("../../frontend/html_src/preview.html");
+const HISTORY: &str = include_str!("../../frontend/html_src/history.html");
 
-pub fn validated(competitions: Vec<Competition>) -> String {
+/// The competitions are given by id and name.
+pub fn validated(competitions: Vec<(String, String)>, csrf_token: &str) -> String {
     let inner = competitions.into_iter()
-        .map(|competition| format!("<a class =  \"style_list\" href = \"/{id}\"><text>{name}</text></a>",
-            id = competition.id(),
-            name = competition.name()))
+        .map(|(id, name)| format!("<a class =  \"style_list\" href = \"/{id}\"><text>{name}</text></a>",
+            name = escape(&name)))
         .collect::<Vec<_>>()
         .join("\n");
-    VALIDATED.replace("COMPETITIONS", &inner)
+    VALIDATED.replace("CSRF_TOKEN", csrf_token)
+        .replace("COMPETITIONS", &inner)
 }
 
 pub fn rounds(rounds: Vec<RoundInfo>, competition_id: &str, stations: u64) -> String {
@@ -24,7 +35,7 @@ pub fn rounds(rounds: Vec<RoundInfo>, competition_id: &str, stations: u64) -> St
                 } else {
                     "style_list"
                 };
-                Some(format!("<a class =  \"{class_style}\" onclick = redirect(\"/{competition_id}/{event}/{round}\")><text>{name} ({entered}/{competitors})</text></a>",
+                Some(format!("<div class = \"batch_row\"><input type = \"checkbox\" class = \"batch\" value = \"{event}-r{round}\"></input><a class =  \"{class_style}\" onclick = redirect(\"/{competition_id}/{event}/{round}\")><text>{name} ({entered}/{competitors})</text></a></div>",
             event = round.event,
             round = round.round_num,
             name = round.print_name()?,
@@ -32,10 +43,140 @@ pub fn rounds(rounds: Vec<RoundInfo>, competition_id: &str, stations: u64) -> St
 	    competitors = round.competitors))})
         .collect::<Vec<_>>()
         .join("\n");
-    ROUNDS.replace("ROUNDS", &inner).replace("STATIONS", &stations.to_string())
+    ROUNDS.replace("ROUNDS", &inner)
+        .replace("STATIONS", &stations.to_string())
+        .replace("COMPETITION_ID", competition_id)
 }
 
 pub fn group(competitors: Competitors, groups_exist: bool) -> String {
     let intermediate = if groups_exist { GROUP.replace("ERROR", "Warning: This round already has groups patched. Make sure that you chose the correct group.")} else { GROUP.replace("ERROR", "")};
     intermediate.replace("DATA", &to_base_64(&competitors))
 }
+
+pub fn constraints(competition_id: &str, persons: &[(u64, String)], constraints: &Constraints, csrf_token: &str) -> String {
+    let name = |id: u64| persons.iter()
+        .find(|(other, _)| *other == id)
+        .map_or_else(|| id.to_string(), |(_, name)| escape(name));
+    let list = |kind: &str, pairs: &[(u64, u64)]| pairs.iter()
+        .map(|&(first, second)| format!("<form class = \"style_list\" method = \"post\"><text>{} and {}</text><input type = \"hidden\" name = \"action\" value = \"remove\"><input type = \"hidden\" name = \"kind\" value = \"{kind}\"><input type = \"hidden\" name = \"first\" value = \"{first}\"><input type = \"hidden\" name = \"second\" value = \"{second}\"><input type = \"hidden\" name = \"csrf\" value = \"{csrf_token}\"><button>Remove</button></form>",
+            name(first),
+            name(second)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let options = persons.iter()
+        .map(|(id, name)| format!("<option value = \"{id}\">{}</option>", escape(name)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    CONSTRAINTS.replace("CSRF_TOKEN", csrf_token)
+        .replace("TOGETHER", &list("together", &constraints.keep_together))
+        .replace("APART", &list("apart", &constraints.keep_apart))
+        .replace("PERSONS", &options)
+        .replace("COMPETITION_ID", competition_id)
+}
+
+pub fn error(status: StatusCode, message: &str) -> String {
+    ERROR.replace("STATUS", status.as_str())
+        .replace("REASON", status.canonical_reason().unwrap_or(""))
+        .replace("MESSAGE", &escape(message))
+}
+
+pub fn logged_out() -> &'static str {
+    LOGGED_OUT
+}
+
+/// Page for starting an offline session, listing the competitions of the wcif directory.
+pub fn offline(competitions: &[String], state: &str) -> String {
+    let inner = competitions.iter()
+        .map(|id| format!("<form class = \"style_list\" method = \"post\" action = \"/offline/{id}\"><input type = \"hidden\" name = \"state\" value = \"{state}\"><button>{id}</button></form>"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    OFFLINE.replace("STATE", state)
+        .replace("COMPETITIONS", &inner)
+}
+
+/// Page showing what patching the groups of a round changes, with buttons to go ahead with or without patching. Only
+/// offline the groups are written exactly like in the preview.
+pub fn patch_preview(round: &str, diff: &WcifDiff, data: &str, csrf_token: &str, pdf_url: &str, offline: bool) -> String {
+    let note = if offline {
+        ""
+    } else {
+        "<text>The groups are written by the scorecard generator, so activity names and ids on the WCA website may differ \
+            slightly from this preview.</text>\n"
+    };
+    let actions = format!("{note}<form method = \"post\" action = \"/pdf\"><input type = \"hidden\" name = \"data\" value = \"{data}\"><input type = \"hidden\" name = \"csrf\" value = \"{csrf_token}\"><button>Patch to wcif and generate scorecards</button></form>\n<a class = \"style_list\" href = \"{pdf_url}\"><text>Only generate scorecards</text></a>");
+    preview(&format!("Patching the groups of {round} changes the wcif like this"), diff, &actions)
+}
+
+/// Page showing what restoring the groups of a snapshot changes, with a button to go ahead.
+pub fn restore_preview(description: &str, diff: &WcifDiff, csrf_token: &str) -> String {
+    let actions = format!("<form method = \"post\"><input type = \"hidden\" name = \"csrf\" value = \"{csrf_token}\"><button>Restore and patch to wcif</button></form>");
+    preview(&format!("Restoring the groups of the snapshot {} changes the wcif like this", escape(description)), diff, &actions)
+}
+
+/// Snapshots of a competition given by id, time and description.
+pub fn history(competition_id: &str, snapshots: &[(u64, String, String)]) -> String {
+    let inner = if snapshots.is_empty() {
+        "<text>Nothing has been patched yet</text>".to_string()
+    } else {
+        snapshots.iter()
+            .map(|(id, time, description)| format!("<a class = \"style_list\" href = \"/{competition_id}/history/{id}\"><text>{time}: {}</text></a>",
+                escape(description)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    HISTORY.replace("SNAPSHOTS", &inner)
+        .replace("COMPETITION_ID", competition_id)
+}
+
+fn preview(title: &str, diff: &WcifDiff, actions: &str) -> String {
+    let list = |changes: &[(Change, String)]| {
+        let items = changes.iter()
+            .map(|(change, text)| {
+                let class = match change {
+                    Change::Added => "added",
+                    Change::Changed => "changed",
+                    Change::Removed => "removed",
+                };
+                format!("<li class = \"{class}\">{} {}</li>", change.sign(), escape(text))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("<ul>{items}</ul>")
+    };
+    let nothing = "<text>No changes</text>".to_string();
+    let activities = if diff.activities.is_empty() { nothing.clone() } else { list(&diff.activities) };
+    let persons = if diff.persons.is_empty() {
+        nothing
+    } else {
+        diff.persons.iter()
+            .map(|(name, changes)| format!("<h4>{}</h4>{}", escape(name), list(changes)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    PREVIEW.replace("TITLE", title)
+        .replace("ACTIVITIES", &activities)
+        .replace("ACTIONS", actions)
+        .replace("PERSONS", &persons)
+}
+
+/// Adds the script warning about the session timing out to a page.
+pub fn with_session_warning(page: String, timeout: Duration) -> String {
+    let warning = (timeout / 4).min(Duration::from_secs(300));
+    page.replace("</body>", &format!("<script src = \"/session.js\" data-timeout = \"{}\" data-warning = \"{}\"></script>\n    </body>",
+        timeout.as_secs(),
+        warning.as_secs()))
+}
+
+/// Adds the csrf token of the session to a page, where scripts read it from.
+pub fn with_csrf_token(page: String, csrf_token: &str) -> String {
+    page.replace("</head>", &format!("    <meta name = \"csrf-token\" content = \"{csrf_token}\">\n</head>"))
+}
+
+/// Escapes text so it can be put inside html.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
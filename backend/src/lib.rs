@@ -0,0 +1,1250 @@
+mod batch;
+mod db;
+mod error;
+mod export;
+mod history;
+mod html;
+mod logging;
+mod metrics;
+mod offline;
+mod reload;
+mod store;
+mod tls;
+mod staff;
+mod wcif;
+
+use actix_web::{
+	body::MessageBody,
+	cookie::{time, Cookie, SameSite},
+	delete,
+	dev::Service, get, post,
+	http::StatusCode,
+	web::{scope, Bytes, Data, Form, FormConfig, Path, PathConfig, PayloadConfig, Query, QueryConfig, ServiceConfig},
+	App, HttpRequest, HttpResponse, HttpResponseBuilder, HttpServer, Responder,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use batch::BatchFormat;
+use common::{decode_base_64, to_base_64, Competitors, PdfRequest, RoundInfo};
+use db::Session;
+pub use db::DB;
+use error::Error;
+use export::{ExportFormat, Row};
+use logging::LogConfig;
+use metrics::MonitoringConfig;
+use futures::future::FutureExt;
+use ring::constant_time::verify_slices_are_equal;
+use scorecard_to_pdf::Return;
+use serde::Deserialize;
+use std::{
+	collections::HashMap,
+	fs::read_to_string,
+	future::Future,
+	panic::{AssertUnwindSafe, UnwindSafe},
+	sync::{atomic::Ordering, Arc},
+	time::{Duration, Instant},
+};
+use tokio::time::interval;
+use wca_oauth::WcifContainer;
+use wca_scorecards_lib::{ScorecardOrdering, Stages};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+	client_id: String,
+	client_secret: String,
+	redirect_uri: String,
+	auth_url: String,
+	/// Pem file with the certificate chain, leaf certificate first. Plain http is served if not set.
+	public_pem_path: Option<String>,
+	/// Pem file with the private key in PKCS#1, PKCS#8 or SEC1 format.
+	private_pem_path: Option<String>,
+	/// Addresses to listen on, such as `127.0.0.1:8080` or `[::]:443`.
+	#[serde(default = "default_bind")]
+	bind: Vec<String>,
+	/// Number of worker threads. Defaults to the number of cpu cores.
+	workers: Option<usize>,
+	pkg_path: String,
+	/// Minutes of inactivity after which a session is logged out.
+	#[serde(default = "default_session_timeout")]
+	session_timeout: u64,
+	/// File the sessions are stored in so they survive a restart. Sessions are only kept in memory if not set.
+	session_path: Option<String>,
+	/// Hex encoded 32 byte key the session file is encrypted with.
+	session_key: Option<String>,
+	/// Directory of wcif files named `<competition id>.json` which can be worked on offline, without logging in to the
+	/// WCA website. Wcif files can also be uploaded when this is not set.
+	wcif_dir: Option<String>,
+	/// Directory where the wcif is saved before every patch of groups, so the patch can be rolled back. The snapshots
+	/// contain the private data of the competitors. They are only kept in memory if not set.
+	snapshot_dir: Option<String>,
+	#[serde(default)]
+	log: LogConfig,
+	#[serde(default)]
+	monitoring: MonitoringConfig,
+}
+
+impl Config {
+	pub fn load(path: &str) -> Result<Config, String> {
+		let data = read_to_string(path).map_err(|e| format!("Could not read config file {path}: {e}"))?;
+		Config::parse(&data).map_err(|e| format!("Config file {path} is not valid: {e}"))
+	}
+
+	pub fn parse(data: &str) -> Result<Config, String> {
+		toml::from_str(data).map_err(|e| e.to_string())
+	}
+}
+
+fn default_session_timeout() -> u64 {
+	60
+}
+
+fn default_bind() -> Vec<String> {
+	vec!["127.0.0.1:8080".to_string()]
+}
+
+fn get_cookie(http: &HttpRequest) -> Option<Cookie<'static>> {
+	http.cookies()
+		.ok()?
+		.to_vec()
+		.into_iter()
+		.find(|c| c.name() == "scorecards")
+}
+
+/// The session of the user making the request.
+async fn session(db: &DB, http: &HttpRequest) -> Result<Arc<Session>, Error> {
+	let cookie = get_cookie(http).ok_or(Error::Unauthenticated)?;
+	db.session(cookie.value()).await.ok_or(Error::SessionExpired)
+}
+
+/// Adds the session timeout warning and the csrf token of the session to a page.
+fn page(db: &DB, session: &Session, body: String) -> String {
+	let body = html::with_csrf_token(body, &session.csrf_token());
+	html::with_session_warning(body, Duration::from_secs(db.config().session_timeout * 60))
+}
+
+/// The csrf token sent by scripts in the `X-CSRF-Token` header.
+fn csrf_header(http: &HttpRequest) -> &str {
+	http.headers()
+		.get("X-CSRF-Token")
+		.and_then(|value| value.to_str().ok())
+		.unwrap_or_default()
+}
+
+fn create_cookie(session: String) -> Cookie<'static> {
+	Cookie::build("scorecards", session)
+		.secure(true)
+		.http_only(true)
+		.same_site(SameSite::Lax)
+		.finish()
+}
+
+/// Checks that a login comes from the browser which started it, by comparing the `state` it sends along with the
+/// state cookie.
+fn check_state(http: &HttpRequest, state: Option<&str>) -> Result<(), Error> {
+	let cookie = http.cookie("scorecards_state");
+	match (cookie, state) {
+		(Some(cookie), Some(state)) if verify_slices_are_equal(cookie.value().as_bytes(), state.as_bytes()).is_ok() => Ok(()),
+		_ => Err(Error::BadRequest("The login could not be verified, please log in again".to_string())),
+	}
+}
+
+/// Sets the session cookie of a new session and removes the state cookie of the login.
+fn logged_in(builder: &mut HttpResponseBuilder, session: String) {
+	let mut state_cookie = Cookie::named("scorecards_state");
+	state_cookie.make_removal();
+	builder.cookie(create_cookie(session));
+	builder.cookie(state_cookie);
+}
+
+/// Cookie holding the `state` of a login in progress. It has to be sent along when the WCA website redirects back,
+/// so it can not be strict.
+fn create_state_cookie(state: String) -> Cookie<'static> {
+	Cookie::build("scorecards_state", state)
+		.secure(true)
+		.http_only(true)
+		.same_site(SameSite::Lax)
+		.max_age(time::Duration::minutes(10))
+		.finish()
+}
+
+async fn catch<F>(future: F) -> HttpResponse
+where
+	F: Future<Output = Result<HttpResponse, Error>> + UnwindSafe,
+{
+	match future.catch_unwind().await {
+		Ok(Ok(r)) => r,
+		Ok(Err(e)) => HttpResponse::from_error(e),
+		Err(e) => {
+			metrics::PANICS.fetch_add(1, Ordering::Relaxed);
+			let error = panic_message::panic_message(&e);
+			HttpResponse::from_error(Error::Internal(error.to_string()))
+		}
+	}
+}
+
+macro_rules! catch {
+    ($($t:tt) *) => {
+        catch(AssertUnwindSafe(async { $($t)* })).await
+    };
+}
+
+#[get("/")]
+async fn root(http: HttpRequest, db: Data<DB>) -> impl Responder {
+	catch!(
+        let mut builder = HttpResponse::build(StatusCode::OK);
+        let body = match get_cookie(&http) {
+            Some(v) if db.logged_in(v.value()) => {
+                "<script>window.location.href=\"validated\"</script>".to_string()
+            }
+            _ => {
+                let config = db.config();
+                // The state ties the login to this browser, so nobody can log someone in with their own code.
+                let state = db::random_token();
+                builder.cookie(create_state_cookie(state.clone()));
+                let separator = if config.auth_url.contains('?') { '&' } else { '?' };
+                format!(
+                    "<script>window.location.href=\"{}{separator}state={state}\"</script>",
+                    &config.auth_url
+                )
+            }
+        };
+        Ok(builder
+            .content_type("html")
+            .message_body(MessageBody::boxed(body))
+            .unwrap()))
+}
+
+#[get("/favicon.ico")]
+async fn favicon() -> impl Responder {
+	catch!(Ok(HttpResponse::build(StatusCode::OK)
+		.content_type("image/jpg")
+		.body(&include_bytes!("../../frontend/favicon.ico")[..])))
+}
+
+#[get("/css")]
+async fn css() -> impl Responder {
+	catch!(Ok(HttpResponse::build(StatusCode::OK)
+		.content_type("text/css")
+		.body(include_str!("../../frontend/html_src/style.css"))))
+}
+
+#[get("/session.js")]
+async fn session_js() -> impl Responder {
+	catch!(Ok(HttpResponse::build(StatusCode::OK)
+		.content_type("text/javascript")
+		.body(include_str!("../../frontend/html_src/session.js"))))
+}
+
+/// Keeps the session alive without doing anything else.
+#[post("/keepalive")]
+async fn keepalive(http: HttpRequest, db: Data<DB>) -> impl Responder {
+	catch!(
+	session(&db, &http).await?.check_csrf(csrf_header(&http))?;
+	Ok(HttpResponse::build(StatusCode::NO_CONTENT).finish()))
+}
+
+/// Checks that the request may see the monitoring endpoints.
+fn check_monitoring_access(db: &DB, http: &HttpRequest) -> Result<(), Error> {
+	let config = db.config();
+	let monitoring = &config.monitoring;
+	let from_allowed_ip = monitoring.allow_ips_without_token && http
+		.peer_addr()
+		.is_some_and(|addr| monitoring.allowed_ips.contains(&addr.ip().to_canonical()));
+	let has_token = monitoring.token.as_ref().is_some_and(|token| {
+		let expected = format!("Bearer {token}");
+		http.headers()
+			.get("Authorization")
+			.is_some_and(|value| verify_slices_are_equal(value.as_bytes(), expected.as_bytes()).is_ok())
+	});
+	if from_allowed_ip || has_token {
+		Ok(())
+	} else {
+		Err(Error::Forbidden)
+	}
+}
+
+#[get("/healthz")]
+async fn healthz(http: HttpRequest, db: Data<DB>) -> impl Responder {
+	catch!(
+	check_monitoring_access(&db, &http)?;
+	Ok(HttpResponse::build(StatusCode::OK)
+		.content_type("text/plain")
+		.body("ok")))
+}
+
+#[get("/metrics")]
+async fn metrics_page(http: HttpRequest, db: Data<DB>) -> impl Responder {
+	catch!(
+	check_monitoring_access(&db, &http)?;
+	Ok(HttpResponse::build(StatusCode::OK)
+		.content_type("text/plain; version=0.0.4")
+		.body(metrics::render(db.session_count(), db.cached_wcif_count()))))
+}
+
+#[derive(Deserialize)]
+struct CodeReceiver {
+	code: Option<String>,
+	state: Option<String>,
+}
+
+#[get("/validated")]
+async fn validated(
+	http: HttpRequest,
+	db: Data<DB>,
+	query: Query<CodeReceiver>,
+) -> impl Responder {
+	catch!(
+    let cookie = get_cookie(&http);
+    let (mut builder, session_id) = match cookie {
+        Some(v) if db.logged_in(v.value()) => {
+            (HttpResponse::build(StatusCode::OK), v.value().to_owned())
+        }
+        _ => {
+            let code = query.code.clone().ok_or_else(|| Error::BadRequest("Missing authorization code".to_string()))?;
+            check_state(&http, query.state.as_deref())?;
+            let session = db.insert_session(code, cookie.as_ref().map(|cookie| cookie.value())).await;
+            let mut builder = HttpResponse::build(StatusCode::OK);
+            logged_in(&mut builder, session.clone());
+            (builder, session)
+        }
+    };
+
+    let now = Utc::now();
+
+    let session = db.session(&session_id).await.ok_or(Error::SessionExpired)?;
+    let my_competitions = if session.is_offline() {
+        session.cached_competitions()
+    } else {
+        let mut oauth = session.oauth().await?;
+        logging::wca_call("get_competitions_managed_by_me", oauth.get_competitions_managed_by_me())
+            .await
+            .into_iter()
+            .filter(|c| date_from_string(&c.start_date) + chrono::Duration::days(7) > now)
+            .map(|c| (c.id().to_owned(), c.name().to_owned()))
+            .collect()
+    };
+
+    let body = html::validated(my_competitions, &session.csrf_token());
+    let body = page(&db, &session, body);
+
+    Ok(builder
+        .content_type("html")
+        .message_body(MessageBody::boxed(body))
+        .unwrap()))
+}
+
+#[derive(Deserialize)]
+struct CsrfForm {
+	csrf: String,
+}
+
+#[post("/logout")]
+async fn logout(http: HttpRequest, db: Data<DB>, form: Form<CsrfForm>) -> impl Responder {
+	catch!(
+	if let Some(cookie) = get_cookie(&http) {
+		if let Some(session) = db.session(cookie.value()).await {
+			session.check_csrf(&form.csrf)?;
+			db.remove_session(cookie.value());
+		}
+	}
+	let mut cookie = Cookie::named("scorecards");
+	cookie.make_removal();
+	Ok(HttpResponse::build(StatusCode::OK)
+		.cookie(cookie)
+		.content_type("html")
+		.body(html::logged_out())))
+}
+
+#[get("/offline")]
+async fn offline_page(db: Data<DB>) -> impl Responder {
+	catch!(
+	let config = db.config();
+	let competitions = config.wcif_dir.as_deref().map(offline::competitions).unwrap_or_default();
+	// Offline logins are tied to the browser like WCA logins, so nobody can start a session for someone else.
+	let state = db::random_token();
+	Ok(HttpResponse::build(StatusCode::OK)
+		.cookie(create_state_cookie(state.clone()))
+		.content_type("html")
+		.message_body(MessageBody::boxed(html::offline(&competitions, &state)))
+		.unwrap()))
+}
+
+/// Starts an offline session with the wcif of a competition and shows its rounds. The WCA login of the session the
+/// browser had, if any, is carried over so scorecards can be generated.
+async fn start_offline_session(db: &DB, http: &HttpRequest, competition_id: &str, wcif: WcifContainer) -> HttpResponse {
+	let mut oauth = None;
+	if let Some(cookie) = get_cookie(http) {
+		oauth = db.take_oauth(cookie.value()).await;
+		db.remove_session(cookie.value());
+	}
+	let session = db.insert_offline_session(competition_id, wcif, oauth);
+	let mut builder = HttpResponse::build(StatusCode::SEE_OTHER);
+	logged_in(&mut builder, session);
+	builder.insert_header(("Location", format!("/{competition_id}"))).finish()
+}
+
+#[derive(Deserialize)]
+struct StateQuery {
+	state: Option<String>,
+}
+
+/// Starts an offline session with an uploaded wcif file.
+#[post("/offline")]
+async fn upload_wcif(
+	http: HttpRequest,
+	db: Data<DB>,
+	query: Query<StateQuery>,
+	body: Bytes,
+) -> impl Responder {
+	catch!(
+	check_state(&http, query.state.as_deref())?;
+	let (competition_id, wcif) = offline::parse(&body)?;
+	Ok(start_offline_session(&db, &http, &competition_id, wcif).await))
+}
+
+/// Starts an offline session with a wcif file of the wcif directory.
+#[post("/offline/{competition_id}")]
+async fn open_wcif(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<String>,
+	form: Form<StateQuery>,
+) -> impl Responder {
+	catch!(
+	check_state(&http, form.state.as_deref())?;
+	let competition_id = path.into_inner();
+	let config = db.config();
+	let dir = config.wcif_dir.as_deref().ok_or_else(|| Error::CompetitionNotFound(competition_id.clone()))?;
+	let wcif = offline::load(dir, &competition_id)?;
+	Ok(start_offline_session(&db, &http, &competition_id, wcif).await))
+}
+
+/// The wcif as changed by this session, so offline work can be synced to the WCA website later.
+#[get("/{competition_id}/wcif")]
+async fn download_wcif(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<String>,
+) -> impl Responder {
+	catch!(
+	let competition_id = path.into_inner();
+	let session = session(&db, &http).await?;
+	let json = session.with_wcif(&competition_id, |wcif| wcif::to_json(wcif)).await?;
+	let data = serde_json::to_vec_pretty(&json).map_err(|e| Error::Internal(e.to_string()))?;
+	Ok(HttpResponse::build(StatusCode::OK)
+		.content_type("application/json")
+		.insert_header(("Content-Disposition", format!("attachment; filename=\"{competition_id}.json\"")))
+		.message_body(MessageBody::boxed(data))
+		.unwrap()))
+}
+
+fn export_response(format: ExportFormat, name: &str, rows: &[Row]) -> HttpResponse {
+	HttpResponse::build(StatusCode::OK)
+		.content_type(format.content_type())
+		.insert_header(("Content-Disposition", format!("attachment; filename=\"{name}.{}\"", format.extension())))
+		.message_body(MessageBody::boxed(format.write(rows)))
+		.unwrap()
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+	format: ExportFormat,
+}
+
+/// Group and station of every competitor in every round, as assigned in the wcif.
+#[get("/{competition_id}/export")]
+async fn export_groups(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<String>,
+	query: Query<ExportQuery>,
+) -> impl Responder {
+	catch!(
+	let competition_id = path.into_inner();
+	let session = session(&db, &http).await?;
+	session.wcif_force_download(&competition_id).await?;
+	let rows = session.with_wcif(&competition_id, |wcif| export::wcif_rows(wcif)).await?;
+	Ok(export_response(query.format, &format!("{competition_id}-groups"), &rows)))
+}
+
+#[derive(Deserialize)]
+struct RoundExportQuery {
+	/// The groups of the editor as a base 64 encoded `PdfRequest`.
+	data: String,
+	format: ExportFormat,
+}
+
+/// The groups of a round as they are in the group editor, before they are patched.
+#[get("/export")]
+async fn export_round(
+	http: HttpRequest,
+	db: Data<DB>,
+	query: Query<RoundExportQuery>,
+) -> impl Responder {
+	catch!(
+	let query = query.into_inner();
+	let request: PdfRequest = decode_base_64(&query.data).map_err(Error::BadRequest)?;
+	let session = session(&db, &http).await?;
+	let rows = session.with_wcif(&request.competition, |wcif| {
+		wcif::check_round(wcif, &request.event, request.round as usize)?;
+		Ok::<_, Error>(export::round_rows(wcif, &request.event, request.round, &request.groups))
+	}).await??;
+	let name = format!("{}-{}-r{}-groups", request.competition, request.event, request.round);
+	Ok(export_response(query.format, &name, &rows)))
+}
+
+fn date_from_string(date: &str) -> DateTime<Utc> {
+	let iter: Vec<_> = date.split('-').collect();
+	Utc.with_ymd_and_hms(
+		iter[0].parse().unwrap(),
+		iter[1].parse().unwrap(),
+		iter[2].parse().unwrap(),
+		0,
+		0,
+		0,
+	)
+	.unwrap()
+}
+
+#[get("/{competition_id}")]
+async fn competition(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<String>,
+) -> impl Responder {
+	catch!(
+    let session = session(&db, &http).await?;
+    let id = path.into_inner();
+    session.wcif_force_download(&id).await?;
+    let body = session.with_wcif(&id, |wcif| {
+    let rounds: Vec<RoundInfo> = wcif
+        .round_iter()
+        .map(|r| {
+            let mut event_round_split = r.id.split('-');
+            let event = event_round_split.next().unwrap();
+            let round_num = event_round_split.next().unwrap()[1..].parse().unwrap();
+	    let (entered, competitors) = wcif.count_entered(event, round_num as usize);
+            RoundInfo {
+                event: event.to_owned(),
+                round_num,
+                groups_exist: wcif.detect_round_groups_exist(event, round_num as usize),
+		entered,
+		competitors,
+            }
+        })
+        .collect();
+
+    let stations = wcif.get().extensions.iter().find(|ext| ext.get("id") == Some(&serde_json::Value::String("dve.CompetitionConfig".to_string())))
+        .and_then(|ext| ext.get("data"))
+        .and_then(|data| data.get("stations"))
+        .and_then(|stations| stations.as_u64())
+        .unwrap_or(10);
+
+    html::rounds(rounds, &wcif.get().id, stations)
+    }).await?;
+    let body = page(&db, &session, body);
+    let mut builder = HttpResponse::build(StatusCode::OK);
+    Ok(builder
+        .content_type("html")
+        .message_body(MessageBody::boxed(body))
+        .unwrap()))
+}
+
+#[get("/{competition_id}/constraints")]
+async fn constraints_page(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<String>,
+) -> impl Responder {
+	catch!(
+	let competition_id = path.into_inner();
+	let session = session(&db, &http).await?;
+	let body = session
+		.with_wcif(&competition_id, |wcif| html::constraints(&competition_id, &wcif::person_names(wcif), &wcif::constraints(wcif), &session.csrf_token()))
+		.await?;
+	let body = page(&db, &session, body);
+	Ok(HttpResponse::build(StatusCode::OK)
+		.content_type("html")
+		.message_body(MessageBody::boxed(body))
+		.unwrap()))
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ConstraintAction {
+	Add,
+	Remove,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ConstraintKind {
+	Together,
+	Apart,
+}
+
+#[derive(Deserialize)]
+struct ConstraintForm {
+	action: ConstraintAction,
+	kind: ConstraintKind,
+	first: u64,
+	second: u64,
+	csrf: String,
+}
+
+#[post("/{competition_id}/constraints")]
+async fn update_constraints(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<String>,
+	form: Form<ConstraintForm>,
+) -> impl Responder {
+	catch!(
+	let competition_id = path.into_inner();
+	let form = form.into_inner();
+	let session = session(&db, &http).await?;
+	session.check_csrf(&form.csrf)?;
+	// Work on a fresh wcif so that changes made elsewhere are not overwritten by the patch.
+	session.wcif_force_download(&competition_id).await?;
+	session.with_wcif(&competition_id, |wcif| {
+	let mut constraints = wcif::constraints(wcif);
+	let pairs = match form.kind {
+		ConstraintKind::Together => &mut constraints.keep_together,
+		ConstraintKind::Apart => &mut constraints.keep_apart,
+	};
+	let pair = (form.first.min(form.second), form.first.max(form.second));
+	pairs.retain(|p| *p != pair);
+	if form.action == ConstraintAction::Add && pair.0 != pair.1 {
+		pairs.push(pair);
+	}
+	wcif::set_constraints(wcif, &constraints);
+	}).await?;
+	session.patch_wcif(&competition_id).await?;
+	if session.is_offline() {
+		// Offline wcifs only survive a restart in the session store.
+		db.save();
+	}
+	Ok(HttpResponse::build(StatusCode::SEE_OTHER)
+		.insert_header(("Location", format!("/{competition_id}/constraints")))
+		.finish()))
+}
+
+#[derive(Deserialize)]
+struct StagesQuery {
+	stages: u64,
+	stations: u64,
+	seperate_stages: bool,
+	#[serde(default)]
+	seeded: bool,
+}
+
+#[get("/{competition_id}/{event_id}/{round_no}")]
+async fn round(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<(String, String, usize)>,
+	query: Query<StagesQuery>,
+) -> impl Responder {
+	catch!(
+    let (competition_id, event_id, round_no) = path.into_inner();
+    let session = session(&db, &http).await?;
+    let round_key = (competition_id.clone(), event_id.clone(), round_no as u64);
+    let draft = session.draft(&round_key);
+    let draft_revision = session.draft_revision(&round_key);
+    let stages = query.into_inner();
+    let body = session.with_wcif(&competition_id, |wcif| {
+    wcif::check_round(wcif, &event_id, round_no)?;
+    let delegates = wcif.reg_ids_of_delegates();
+    let (competitors, names) =
+        wca_scorecards_lib::wcif::wca_live_get_competitors_for_round(wcif, &event_id, round_no);
+    // Couple of bad lines needed because of some stuff using usize and some using u64
+    let delegates_u64 = delegates.into_iter().map(|x| x as u64).collect();
+    let competitors_u64 = competitors.into_iter().map(|x| x as u64).collect();
+    let names_u64 = names.into_iter().map(|(k, v)| (k as u64, v)).collect();
+
+    let groups_exist = wcif.detect_round_groups_exist(&event_id, round_no);
+    let rankings = wcif::rankings(wcif, &event_id, round_no);
+    let round_window = wcif::round_window(wcif, &event_id, round_no);
+    let busy = wcif::busy_windows(wcif, &event_id, round_no);
+    let constraints = wcif::constraints(wcif);
+    let newcomers = wcif::newcomers(wcif);
+    let comp_struct = Competitors {
+        competition: competition_id.clone(),
+        competitors: competitors_u64,
+        names: names_u64,
+        delegates: delegates_u64,
+        newcomers,
+        stages: stages.stages,
+        stations: stages.stations,
+        event: event_id.clone(),
+        round: round_no as u64,
+	seperate_stages: stages.seperate_stages,
+        rankings,
+        seeded: stages.seeded,
+        round_window,
+        busy,
+        draft,
+        draft_revision,
+        constraints,
+    };
+
+    Ok(html::group(comp_struct, groups_exist))
+    }).await??;
+    // Groups changed on the WCA website after this are not overwritten by a patch.
+    session.set_base(&competition_id);
+    let body = page(&db, &session, body);
+    let mut builder = HttpResponse::build(StatusCode::OK);
+    Ok(builder
+        .content_type("html")
+        .message_body(MessageBody::boxed(body))
+        .unwrap()))
+}
+
+#[derive(Deserialize)]
+struct DraftQuery {
+	/// Counts the changes in the editor. Requests with an older revision than the last one are ignored.
+	revision: u64,
+}
+
+#[post("/{competition_id}/{event_id}/{round_no}/draft")]
+async fn save_draft(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<(String, String, u64)>,
+	query: Query<DraftQuery>,
+	body: String,
+) -> impl Responder {
+	catch!(
+	let groups: Vec<Vec<u64>> = decode_base_64(&body).map_err(Error::BadRequest)?;
+	let session = session(&db, &http).await?;
+	session.check_csrf(csrf_header(&http))?;
+	if session.set_draft(path.into_inner(), query.revision, groups) {
+		db.save();
+	}
+	Ok(HttpResponse::build(StatusCode::NO_CONTENT).finish()))
+}
+
+#[delete("/{competition_id}/{event_id}/{round_no}/draft")]
+async fn discard_draft(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<(String, String, u64)>,
+	query: Query<DraftQuery>,
+) -> impl Responder {
+	catch!(
+	let session = session(&db, &http).await?;
+	session.check_csrf(csrf_header(&http))?;
+	if session.discard_draft(path.into_inner(), query.revision) {
+		db.save();
+	}
+	Ok(HttpResponse::build(StatusCode::NO_CONTENT).finish()))
+}
+
+#[derive(Deserialize)]
+struct PdfRequest64 {
+	data: String,
+}
+
+#[derive(Deserialize)]
+struct PatchForm {
+	data: String,
+	csrf: String,
+}
+
+/// Writes the judge, scrambler and runner assignments of a pdf request to the wcif.
+fn set_staff(wcif: &mut WcifContainer, pdf_request: &PdfRequest, rankings: &HashMap<u64, u64>) {
+	let staff = staff::assign_staff(
+		&pdf_request.groups,
+		pdf_request.stages,
+		pdf_request.stations,
+		pdf_request.seperate_stages,
+		rankings,
+	);
+	wcif::set_staff_assignments(wcif, &pdf_request.event, pdf_request.round as usize, &staff);
+}
+
+#[derive(Deserialize)]
+struct PreviewQuery {
+	data: String,
+}
+
+/// Shows what patching the groups of a pdf request changes in the wcif before anything is patched.
+#[get("/pdf/preview")]
+async fn pdf_preview(
+	http: HttpRequest,
+	query: Query<PreviewQuery>,
+	db: Data<DB>,
+) -> impl Responder {
+	catch!(
+	let pdf_request: PdfRequest = decode_base_64(&query.data).map_err(Error::BadRequest)?;
+	let session = session(&db, &http).await?;
+	let round_id = format!("{}-r{}", pdf_request.event, pdf_request.round);
+	// The preview starts from the same current wcif as the patch, and fails the same way if the round was changed.
+	session.refresh_for_patch(&pdf_request.competition, std::slice::from_ref(&round_id)).await?;
+	let diff = session.with_wcif(&pdf_request.competition, |wcif| {
+		let round_no = pdf_request.round as usize;
+		wcif::check_round(wcif, &pdf_request.event, round_no)?;
+		// The scorecard generator writes the groups when patching, so this only shows what it is expected to write.
+		let mut planned = wcif::copy(wcif);
+		wcif::set_groups(&mut planned, &pdf_request.event, round_no, &pdf_request.groups);
+		if pdf_request.staff {
+			set_staff(&mut planned, &pdf_request, &wcif::rankings(wcif, &pdf_request.event, round_no));
+		}
+		Ok(wcif::diff(wcif, &planned))
+	}).await??;
+	let pdf_url = format!("/pdf?data={}", to_base_64(PdfRequest { wcif: false, staff: false, ..pdf_request }));
+	let body = page(&db, &session, html::patch_preview(&round_id, &diff, &query.data, &session.csrf_token(), &pdf_url, session.is_offline()));
+	Ok(HttpResponse::build(StatusCode::OK)
+		.content_type("html")
+		.message_body(MessageBody::boxed(body))
+		.unwrap()))
+}
+
+/// Generates the scorecards of a round. Requests which patch the groups are sent to the preview instead, which
+/// patches through a form.
+#[get("pdf")]
+async fn pdf(
+	http: HttpRequest,
+	query: Query<PdfRequest64>,
+	db: Data<DB>,
+) -> impl Responder {
+	catch!(
+	let pdf_request: PdfRequest = decode_base_64(&query.data).map_err(Error::BadRequest)?;
+	if pdf_request.wcif {
+		return Ok(HttpResponse::build(StatusCode::SEE_OTHER)
+			.insert_header(("Location", format!("/pdf/preview?data={}", query.data)))
+			.finish());
+	}
+	let session = session(&db, &http).await?;
+	scorecards(&db, &session, pdf_request).await)
+}
+
+/// Patches the groups of a round after the user confirmed the preview, and generates its scorecards.
+#[post("pdf")]
+async fn patch_pdf(
+	http: HttpRequest,
+	form: Form<PatchForm>,
+	db: Data<DB>,
+) -> impl Responder {
+	catch!(
+	let session = session(&db, &http).await?;
+	session.check_csrf(&form.csrf)?;
+	let pdf_request: PdfRequest = decode_base_64(&form.data).map_err(Error::BadRequest)?;
+	scorecards(&db, &session, pdf_request).await)
+}
+
+async fn scorecards(db: &DB, session: &Session, pdf_request: PdfRequest) -> Result<HttpResponse, Error> {
+	let stages = Stages::new(pdf_request.stages as u32, pdf_request.stations as u32, pdf_request.seperate_stages);
+	// Offline the groups are written to the cached wcif instead of being patched.
+	let patch = pdf_request.wcif && !session.is_offline();
+	if patch {
+		session.refresh_for_patch(&pdf_request.competition, &[format!("{}-r{}", pdf_request.event, pdf_request.round)]).await?;
+	}
+	let wcif = session.remove_wcif(&pdf_request.competition).await?;
+	let lent = match wcif::check_round(&wcif, &pdf_request.event, pdf_request.round as usize) {
+		Ok(_) => session.lend_oauth().await,
+		Err(e) => Err(e),
+	};
+	let (lent, oauth) = match lent {
+		Ok(lent) => lent,
+		Err(e) => {
+			session.insert_wcif(&pdf_request.competition, wcif);
+			return Err(e);
+		}
+	};
+	let rankings = wcif::rankings(&wcif, &pdf_request.event, pdf_request.round as usize);
+	if patch {
+		db.history().save(&pdf_request.competition, format!("Before patching the groups of {}-r{}", pdf_request.event, pdf_request.round), &wcif);
+	}
+	let mut wcif_oauth = wcif.add_oauth(oauth);
+	let generated = wca_scorecards_lib::generate_pdf(
+		&pdf_request.event,
+		pdf_request.round as usize,
+		pdf_request
+			.groups
+			.iter()
+			.map(|z| z.iter().map(|&z| z as usize).collect())
+			.collect(),
+		patch,
+		&mut wcif_oauth,
+		&stages,
+		ScorecardOrdering::Default,
+	)
+	.await;
+	metrics::pdf_generated(patch);
+	let (mut wcif, mut oauth) = wcif_oauth.disassemble();
+	if pdf_request.wcif && session.is_offline() {
+		wcif::set_groups(&mut wcif, &pdf_request.event, pdf_request.round as usize, &pdf_request.groups);
+	}
+	let mut patched = Ok(());
+	if pdf_request.wcif && pdf_request.staff {
+		set_staff(&mut wcif, &pdf_request, &rankings);
+		if patch {
+			let wcif_oauth = wcif.add_oauth(oauth);
+			patched = logging::wca_call("patch", wcif_oauth.patch()).await.map(|_| ()).map_err(|e| Error::Wca(format!("{e:?}")));
+			metrics::WCIF_PATCHES.fetch_add(1, Ordering::Relaxed);
+			(wcif, oauth) = wcif_oauth.disassemble();
+		}
+	}
+	lent.give_back(oauth);
+	session.insert_wcif(&pdf_request.competition, wcif);
+	patched?;
+	if patch {
+		session.set_base(&pdf_request.competition);
+	}
+	if pdf_request.wcif {
+		// The groups are in the wcif now, so the draft is no longer needed.
+		session.remove_draft(&(pdf_request.competition.clone(), pdf_request.event.clone(), pdf_request.round));
+		db.save();
+	}
+	Ok(match generated {
+		Return::Pdf(z) => HttpResponse::build(StatusCode::OK)
+			.content_type("application/pdf")
+			.message_body(MessageBody::boxed(z))
+			.unwrap(),
+		Return::Zip(z) => HttpResponse::build(StatusCode::OK)
+			.content_type("application/zip")
+			.message_body(MessageBody::boxed(z))
+			.unwrap(),
+	})
+}
+
+#[derive(Deserialize)]
+struct BatchQuery {
+	stages: u64,
+	stations: u64,
+	seperate_stages: bool,
+	#[serde(default)]
+	seeded: bool,
+	/// Patching is refused, as it is only done from the group editor after a preview of the changes.
+	#[serde(default)]
+	wcif: bool,
+	/// Comma separated round ids such as `333-r1`.
+	rounds: String,
+	format: BatchFormat,
+}
+
+#[get("/{competition_id}/batch")]
+async fn batch_pdf(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<String>,
+	query: Query<BatchQuery>,
+) -> impl Responder {
+	catch!(
+	let competition_id = path.into_inner();
+	let query = query.into_inner();
+	if query.stages == 0 || query.stations == 0 {
+		return Err(Error::BadRequest("There has to be at least one stage with at least one station".to_string()));
+	}
+	let stages = Stages::new(query.stages as u32, query.stations as u32, query.seperate_stages);
+	let rounds = query.rounds
+		.split(',')
+		.map(|round_id| batch::parse_round(round_id)
+			.map(|(event, round_no)| (round_id, event, round_no))
+			.ok_or_else(|| Error::BadRequest(format!("{round_id} is not a round id of the form 333-r1"))))
+		.collect::<Result<Vec<_>, _>>()?;
+	if query.wcif {
+		return Err(Error::BadRequest("Groups can only be patched one round at a time from the group editor, \
+			which shows the changes first.".to_string()));
+	}
+	let session = session(&db, &http).await?;
+	let wcif = session.remove_wcif(&competition_id).await?;
+	let lent = match rounds.iter().find_map(|(_, event, round_no)| wcif::check_round(&wcif, event, *round_no).err()) {
+		None => session.lend_oauth().await,
+		Some(e) => Err(e),
+	};
+	let (lent, oauth) = match lent {
+		Ok(lent) => lent,
+		Err(e) => {
+			session.insert_wcif(&competition_id, wcif);
+			return Err(e);
+		}
+	};
+	let mut wcif_oauth = wcif.add_oauth(oauth);
+	let mut files = Vec::new();
+	for (round_id, event, round_no) in rounds {
+		let (mut wcif, oauth) = wcif_oauth.disassemble();
+		let groups = batch::auto_groups(&mut wcif, event, round_no, query.stages, query.stations, query.seeded);
+		wcif_oauth = wcif.add_oauth(oauth);
+		let Some(groups) = groups else {
+			continue;
+		};
+		let generated = wca_scorecards_lib::generate_pdf(
+			event,
+			round_no,
+			groups,
+			false,
+			&mut wcif_oauth,
+			&stages,
+			ScorecardOrdering::Default,
+		)
+		.await;
+		metrics::pdf_generated(false);
+		files.extend(batch::pdf_files(round_id, generated));
+	}
+	let (wcif, oauth) = wcif_oauth.disassemble();
+	lent.give_back(oauth);
+	session.insert_wcif(&competition_id, wcif);
+	if files.is_empty() {
+		return Err(Error::BadRequest("None of the selected rounds have any competitors yet".to_string()));
+	}
+	Ok(match query.format {
+		BatchFormat::Pdf => HttpResponse::build(StatusCode::OK)
+			.content_type("application/pdf")
+			.message_body(MessageBody::boxed(batch::merge_pdfs(files.into_iter().map(|(_, data)| data).collect())))
+			.unwrap(),
+		BatchFormat::Zip => HttpResponse::build(StatusCode::OK)
+			.content_type("application/zip")
+			.message_body(MessageBody::boxed(batch::zip_files(files)))
+			.unwrap(),
+	}))
+}
+
+#[get("/{competition_id}/history")]
+async fn history_page(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<String>,
+) -> impl Responder {
+	catch!(
+	let competition_id = path.into_inner();
+	let session = session(&db, &http).await?;
+	// Only those who can see the wcif may see its snapshots.
+	session.with_wcif(&competition_id, |_| ()).await?;
+	let snapshots: Vec<_> = db.history()
+		.list(&competition_id)
+		.into_iter()
+		.map(|(id, description)| (id, history::time(id), description))
+		.collect();
+	let body = page(&db, &session, html::history(&competition_id, &snapshots));
+	Ok(HttpResponse::build(StatusCode::OK)
+		.content_type("html")
+		.message_body(MessageBody::boxed(body))
+		.unwrap()))
+}
+
+/// The current wcif with the groups of the snapshot put back.
+async fn restored_wcif(db: &DB, session: &Session, competition_id: &str, snapshot_id: u64) -> Result<(WcifContainer, WcifContainer, String), Error> {
+	let snapshot = db.history().get(competition_id, snapshot_id).ok_or(Error::SnapshotNotFound(snapshot_id))?;
+	let description = format!("{} ({})", snapshot.time(), snapshot.description);
+	let old = wcif::from_value(snapshot.wcif).map_err(Error::Internal)?;
+	let current = session.with_wcif(competition_id, |wcif| wcif::copy(wcif)).await?;
+	let mut restored = wcif::copy(&current);
+	wcif::restore_groups(&mut restored, &old);
+	Ok((current, restored, description))
+}
+
+/// Shows what restoring the groups of a snapshot changes.
+#[get("/{competition_id}/history/{snapshot_id}")]
+async fn snapshot_page(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<(String, u64)>,
+) -> impl Responder {
+	catch!(
+	let (competition_id, snapshot_id) = path.into_inner();
+	let session = session(&db, &http).await?;
+	session.wcif_force_download(&competition_id).await?;
+	let (current, restored, description) = restored_wcif(&db, &session, &competition_id, snapshot_id).await?;
+	let body = html::restore_preview(&description, &wcif::diff(&current, &restored), &session.csrf_token());
+	let body = page(&db, &session, body);
+	Ok(HttpResponse::build(StatusCode::OK)
+		.content_type("html")
+		.message_body(MessageBody::boxed(body))
+		.unwrap()))
+}
+
+/// Puts back the groups of a snapshot and patches them to the wcif.
+#[post("/{competition_id}/history/{snapshot_id}")]
+async fn restore_snapshot(
+	http: HttpRequest,
+	db: Data<DB>,
+	path: Path<(String, u64)>,
+	form: Form<CsrfForm>,
+) -> impl Responder {
+	catch!(
+	let (competition_id, snapshot_id) = path.into_inner();
+	let session = session(&db, &http).await?;
+	session.check_csrf(&form.csrf)?;
+	// Work on a fresh wcif so that changes made elsewhere are not overwritten by the patch.
+	session.wcif_force_download(&competition_id).await?;
+	let (current, restored, description) = restored_wcif(&db, &session, &competition_id, snapshot_id).await?;
+	// Restoring is a patch too, so it can be undone the same way.
+	if !session.is_offline() {
+		db.history().save(&competition_id, format!("Before restoring the snapshot {description}"), &current);
+	}
+	session.insert_wcif(&competition_id, restored);
+	session.patch_wcif(&competition_id).await?;
+	if session.is_offline() {
+		db.save();
+	}
+	Ok(HttpResponse::build(StatusCode::SEE_OTHER)
+		.insert_header(("Location", format!("/{competition_id}/history")))
+		.finish()))
+}
+
+#[get("/pkg/{file:.*}")]
+async fn pkg(path: Path<String>, db: Data<DB>) -> impl Responder {
+	catch!(
+    let config = db.config();
+    let pkg_path = &config.pkg_path;
+    let file_path = format!("{pkg_path}/{path}");
+    let mime = if path.ends_with(".js") {
+        "text/javascript"
+    } else if path.ends_with(".wasm") {
+        "application/wasm"
+    } else {
+        return Err(Error::BadRequest(format!("file type is {path}")));
+    };
+    let data = std::fs::read(file_path).map_err(|e| Error::Internal(format!("Could not read {path}: {e}")))?;
+    Ok(HttpResponse::build(StatusCode::OK)
+        .content_type(mime)
+        .message_body(MessageBody::boxed(data))
+        .unwrap()))
+}
+
+/// Creates the database of the app.
+pub fn init(config: Config) -> Result<Data<DB>, String> {
+	Ok(Data::new(DB::new(config)?))
+}
+
+/// Registers every page of the app. The request logging is left to the caller.
+pub fn configure(db: Data<DB>) -> impl FnOnce(&mut ServiceConfig) {
+	move |cfg| {
+		let pages = scope("")
+			// Scripts can not tell the redirect to the login apart from success, so they get a 401 instead.
+			.wrap_fn(|request, service| service.call(request).map(|response| response.map(error::unauthorized_for_scripts)))
+			.service(root)
+			.service(favicon)
+			.service(css)
+			.service(validated)
+			.service(logout)
+			.service(session_js)
+			.service(keepalive)
+			.service(healthz)
+			.service(metrics_page)
+			.service(offline_page)
+			.service(upload_wcif)
+			.service(open_wcif)
+			.service(pkg)
+			.service(pdf_preview)
+			.service(pdf)
+			.service(patch_pdf)
+			.service(batch_pdf)
+			.service(download_wcif)
+			.service(export_round)
+			.service(export_groups)
+			.service(history_page)
+			.service(snapshot_page)
+			.service(restore_snapshot)
+			.service(constraints_page)
+			.service(update_constraints)
+			.service(competition)
+			.service(round)
+			.service(save_draft)
+			.service(discard_draft);
+		cfg.service(pages)
+			.app_data(db)
+			.app_data(QueryConfig::default()
+				.error_handler(|e, _| Error::BadRequest(e.to_string()).into()))
+			.app_data(FormConfig::default()
+				.error_handler(|e, _| Error::BadRequest(e.to_string()).into()))
+			.app_data(PathConfig::default()
+				.error_handler(|e, _| Error::BadRequest(e.to_string()).into()))
+			.app_data(PayloadConfig::new(offline::MAX_WCIF_SIZE));
+	}
+}
+
+/// Runs the server with the config file at `config_path` until it is stopped.
+pub async fn run(config_path: String) -> Result<(), String> {
+	let config = Config::load(&config_path)?;
+
+	logging::init(config.log.clone())?;
+	std::panic::set_hook(Box::new(move |info| {
+		let location = info
+			.location()
+			.map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
+			.unwrap_or_default();
+		log::error!(target: "panic", location = location; "{}", panic_message::panic_info_message(info));
+	}));
+
+	let certificate = match (&config.public_pem_path, &config.private_pem_path) {
+		(Some(public), Some(private)) => Some(Arc::new(tls::ReloadableCertificate::load(public, private)?)),
+		(None, None) => None,
+		_ => return Err("public_pem_path and private_pem_path must either both be set or both be left out".to_string()),
+	};
+	if config.bind.is_empty() {
+		return Err("bind must contain at least one address".to_string());
+	}
+	let db = init(config.clone())?;
+	let db_arc = db.clone();
+	let mut server = HttpServer::new(move || {
+		let db_arc = db_arc.clone();
+		App::new()
+			.configure(configure(db_arc))
+			.wrap_fn(|req, srv| {
+				let start = Instant::now();
+				let method = req.method().to_string();
+				let response = srv.call(req);
+				async move {
+					let response = response.await?;
+					let request = response.request();
+					let route = request.match_pattern().unwrap_or_else(|| request.path().to_owned());
+					let competition_id = request.match_info().get("competition_id").unwrap_or_default().to_owned();
+					let status = response.status();
+					let error = response.response().error().map(|e| e.to_string()).unwrap_or_default();
+					let level = if status.is_server_error() {
+						log::Level::Error
+					} else if status.is_client_error() {
+						log::Level::Warn
+					} else {
+						log::Level::Info
+					};
+					log::log!(
+						target: "request",
+						level,
+						method = method,
+						route = route,
+						competition = competition_id,
+						status = status.as_u16(),
+						latency_ms = start.elapsed().as_millis() as u64,
+						error = error;
+						"{method} {route} {status}"
+					);
+					Ok(response)
+				}
+			})
+	});
+
+	if let Some(workers) = config.workers {
+		if workers == 0 {
+			return Err("workers must be at least 1".to_string());
+		}
+		server = server.workers(workers);
+	}
+	let tls = certificate.clone().map(tls::server_config);
+	for address in &config.bind {
+		server = match &tls {
+			Some(tls) => server.bind_rustls(address.as_str(), tls.clone()),
+			None => server.bind(address.as_str()),
+		}
+		.map_err(|e| format!("Could not listen on {address}: {e}"))?;
+		log::info!("Listening on {address}");
+	}
+
+	tokio::task::spawn(reload::watch(config_path, db.clone(), certificate));
+	tokio::task::spawn(db.clone().into_inner().write_sessions());
+	let cleaned = db.clone();
+	tokio::task::spawn(async move {
+		let mut interval = interval(Duration::from_secs(600));
+		loop {
+			interval.tick().await;
+			cleaned.clean();
+		}
+	});
+
+	let result = server.run().await.map_err(|e| format!("Server stopped: {e}"));
+	// The last changes may not have been written yet.
+	db.flush();
+	result
+}
@@ -0,0 +1,197 @@
+use std::{
+	fs::{rename, File, OpenOptions},
+	future::Future,
+	io::{stderr, Write},
+	sync::Mutex,
+	time::Instant,
+};
+
+use log::{
+	kv::{self, Key, Value, VisitSource},
+	LevelFilter, Log, Metadata, Record,
+};
+use serde::Deserialize;
+
+use crate::metrics::{self, Outcome};
+use serde_json::{Map, Value as Json};
+
+/// The `[log]` table of the config file.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LogConfig {
+	/// Most verbose level which is logged, e.g. `info` or `debug`.
+	pub level: LevelFilter,
+	/// File to log to. Logs go to stderr if it is not set.
+	pub path: Option<String>,
+	/// Write every record as a json object on its own line instead of plain text.
+	pub json: bool,
+	/// Size in bytes after which the log file is rotated.
+	pub max_size: u64,
+	/// Number of rotated files kept next to the log file, named `path.1`, `path.2` and so on.
+	pub max_files: usize,
+}
+
+impl Default for LogConfig {
+	fn default() -> LogConfig {
+		LogConfig {
+			level: LevelFilter::Info,
+			path: None,
+			json: false,
+			max_size: 10 * 1024 * 1024,
+			max_files: 5,
+		}
+	}
+}
+
+enum Output {
+	Stderr,
+	File { file: File, size: u64 },
+}
+
+struct Logger {
+	config: LogConfig,
+	output: Mutex<Output>,
+}
+
+/// Installs the global logger.
+pub fn init(config: LogConfig) -> Result<(), String> {
+	let output = match &config.path {
+		Some(path) => {
+			let file = open(path).map_err(|e| format!("Could not open log file {path}: {e}"))?;
+			let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+			Output::File { file, size }
+		}
+		None => Output::Stderr,
+	};
+	log::set_max_level(config.level);
+	log::set_boxed_logger(Box::new(Logger {
+		config,
+		output: Mutex::new(output),
+	}))
+	.map_err(|e| format!("Could not install logger: {e}"))
+}
+
+/// Changes the level of the installed logger.
+pub fn set_level(level: LevelFilter) {
+	log::set_max_level(level);
+}
+
+fn open(path: &str) -> std::io::Result<File> {
+	OpenOptions::new().create(true).append(true).open(path)
+}
+
+impl Logger {
+	fn format(&self, record: &Record) -> String {
+		let time = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+		let mut fields = Fields(Vec::new());
+		let _ = record.key_values().visit(&mut fields);
+		if self.config.json {
+			let mut object = Map::new();
+			object.insert("time".to_string(), Json::String(time));
+			object.insert("level".to_string(), Json::String(record.level().to_string()));
+			object.insert("target".to_string(), Json::String(record.target().to_string()));
+			object.insert("message".to_string(), Json::String(record.args().to_string()));
+			object.extend(fields.0);
+			format!("{}\n", Json::Object(object))
+		} else {
+			let mut line = format!("{time} {:5} {}: {}", record.level(), record.target(), record.args());
+			for (key, value) in fields.0 {
+				match value {
+					Json::String(value) => line += &format!(" {key}={value:?}"),
+					value => line += &format!(" {key}={value}"),
+				}
+			}
+			line + "\n"
+		}
+	}
+
+	/// Moves `path` to `path.1`, `path.1` to `path.2` and so on, dropping the oldest file.
+	fn rotate(&self, path: &str) -> std::io::Result<File> {
+		for idx in (1..self.config.max_files).rev() {
+			let _ = rename(format!("{path}.{idx}"), format!("{path}.{}", idx + 1));
+		}
+		if self.config.max_files == 0 {
+			std::fs::remove_file(path)?;
+		} else {
+			rename(path, format!("{path}.1"))?;
+		}
+		open(path)
+	}
+}
+
+impl Log for Logger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		metadata.level() <= log::max_level()
+	}
+
+	fn log(&self, record: &Record) {
+		if !self.enabled(record.metadata()) {
+			return;
+		}
+		let line = self.format(record);
+		// Logging happens from the panic hook as well, so a poisoned lock must not panic again.
+		let mut output = self.output.lock().unwrap_or_else(|e| e.into_inner());
+		match &mut *output {
+			Output::Stderr => {
+				let _ = stderr().write_all(line.as_bytes());
+			}
+			Output::File { file, size } => {
+				if *size > 0 && *size + line.len() as u64 > self.config.max_size {
+					let path = self.config.path.as_ref().expect("File output has a path");
+					match self.rotate(path) {
+						Ok(new_file) => {
+							*file = new_file;
+							*size = 0;
+						}
+						Err(e) => {
+							let _ = writeln!(stderr(), "Could not rotate log file {path}: {e}");
+						}
+					}
+				}
+				if file.write_all(line.as_bytes()).is_ok() {
+					*size += line.len() as u64;
+				}
+			}
+		}
+	}
+
+	fn flush(&self) {
+		if let Output::File { file, .. } = &mut *self.output.lock().unwrap_or_else(|e| e.into_inner()) {
+			let _ = file.flush();
+		}
+	}
+}
+
+struct Fields(Vec<(String, Json)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+	fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+		let value = if let Some(value) = value.to_u64() {
+			Json::from(value)
+		} else if let Some(value) = value.to_i64() {
+			Json::from(value)
+		} else if let Some(value) = value.to_f64() {
+			Json::from(value)
+		} else if let Some(value) = value.to_bool() {
+			Json::from(value)
+		} else {
+			Json::String(value.to_string())
+		};
+		self.0.push((key.to_string(), value));
+		Ok(())
+	}
+}
+
+/// Awaits a call to the WCA API, logs how long it took and records it in the metrics.
+pub async fn wca_call<F>(call: &'static str, future: F) -> F::Output
+where
+	F: Future,
+	F::Output: Outcome,
+{
+	let start = Instant::now();
+	let output = future.await;
+	let latency = start.elapsed();
+	log::info!(target: "wca", call = call, latency_ms = latency.as_millis() as u64, failed = output.failed(); "WCA API call");
+	metrics::record_wca_call(call, latency, output.failed());
+	output
+}
@@ -0,0 +1,122 @@
+use std::{
+	collections::BTreeMap,
+	fmt::Write,
+	net::IpAddr,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex,
+	},
+	time::Duration,
+};
+
+use serde::Deserialize;
+use wca_oauth::OAuth;
+
+/// The `[monitoring]` table of the config file. A request may see `/healthz` and `/metrics` if it carries the token
+/// as `Authorization: Bearer <token>`. Nobody may see them by default.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct MonitoringConfig {
+	pub token: Option<String>,
+	/// Lets requests from these addresses in without the token, but only if `allow_ips_without_token` is set.
+	pub allowed_ips: Vec<IpAddr>,
+	/// The address is the one of the connection, so behind a reverse proxy every request comes from the proxy and this
+	/// lets everyone in. Only set it if the server is reached directly.
+	pub allow_ips_without_token: bool,
+}
+
+pub static PDF_GENERATIONS: AtomicU64 = AtomicU64::new(0);
+pub static WCIF_PATCHES: AtomicU64 = AtomicU64::new(0);
+pub static PANICS: AtomicU64 = AtomicU64::new(0);
+static WCA_CALLS: Mutex<BTreeMap<&'static str, Calls>> = Mutex::new(BTreeMap::new());
+
+/// Upper bounds in seconds of the WCA API latency histogram buckets.
+const BUCKETS: [f64; 8] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Calls {
+	buckets: [u64; BUCKETS.len()],
+	count: u64,
+	seconds: f64,
+	errors: u64,
+}
+
+/// Whether the result of a WCA API call is a failure.
+pub trait Outcome {
+	fn failed(&self) -> bool {
+		false
+	}
+}
+
+impl<T, E> Outcome for Result<T, E> {
+	fn failed(&self) -> bool {
+		self.is_err()
+	}
+}
+
+impl<T> Outcome for Vec<T> {}
+
+impl Outcome for OAuth {}
+
+/// Counts a generated pdf. Generating a pdf also patches the groups to the wcif if asked to.
+pub fn pdf_generated(wcif: bool) {
+	PDF_GENERATIONS.fetch_add(1, Ordering::Relaxed);
+	if wcif {
+		WCIF_PATCHES.fetch_add(1, Ordering::Relaxed);
+	}
+}
+
+pub fn record_wca_call(call: &'static str, latency: Duration, failed: bool) {
+	let mut calls = WCA_CALLS.lock().unwrap_or_else(|e| e.into_inner());
+	let calls = calls.entry(call).or_default();
+	let seconds = latency.as_secs_f64();
+	for (bucket, bound) in calls.buckets.iter_mut().zip(BUCKETS) {
+		if seconds <= bound {
+			*bucket += 1;
+		}
+	}
+	calls.count += 1;
+	calls.seconds += seconds;
+	calls.errors += failed as u64;
+}
+
+/// All metrics in the Prometheus text format.
+pub fn render(sessions: usize, cached_wcifs: usize) -> String {
+	let mut out = String::new();
+	let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+		let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}");
+	};
+	metric("scorecards_sessions", "gauge", "Logged in sessions.", sessions as u64);
+	metric("scorecards_cached_wcifs", "gauge", "Wcifs cached by all sessions.", cached_wcifs as u64);
+	metric(
+		"scorecards_pdf_generations_total",
+		"counter",
+		"Scorecard pdfs generated, counting every round of a batch.",
+		PDF_GENERATIONS.load(Ordering::Relaxed),
+	);
+	metric(
+		"scorecards_wcif_patches_total",
+		"counter",
+		"Wcifs patched to the WCA website.",
+		WCIF_PATCHES.load(Ordering::Relaxed),
+	);
+	metric("scorecards_panics_total", "counter", "Handler panics caught.", PANICS.load(Ordering::Relaxed));
+
+	let calls = WCA_CALLS.lock().unwrap_or_else(|e| e.into_inner());
+	out += "# HELP scorecards_wca_request_duration_seconds Latency of WCA API calls.\n";
+	out += "# TYPE scorecards_wca_request_duration_seconds histogram\n";
+	for (call, calls) in calls.iter() {
+		for (bound, count) in BUCKETS.iter().zip(calls.buckets) {
+			let _ = writeln!(out, "scorecards_wca_request_duration_seconds_bucket{{call=\"{call}\",le=\"{bound}\"}} {count}");
+		}
+		let _ = writeln!(out, "scorecards_wca_request_duration_seconds_bucket{{call=\"{call}\",le=\"+Inf\"}} {}", calls.count);
+		let _ = writeln!(out, "scorecards_wca_request_duration_seconds_sum{{call=\"{call}\"}} {}", calls.seconds);
+		let _ = writeln!(out, "scorecards_wca_request_duration_seconds_count{{call=\"{call}\"}} {}", calls.count);
+	}
+	out += "# HELP scorecards_wca_errors_total Failed WCA API calls.\n";
+	out += "# TYPE scorecards_wca_errors_total counter\n";
+	for (call, calls) in calls.iter() {
+		let _ = writeln!(out, "scorecards_wca_errors_total{{call=\"{call}\"}} {}", calls.errors);
+	}
+	out
+}
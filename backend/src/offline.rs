@@ -0,0 +1,54 @@
+use std::fs::{read, read_dir};
+
+use wca_oauth::WcifContainer;
+
+use crate::{error::Error, wcif};
+
+/// Largest wcif file accepted. The wcif of a big championship is a few megabytes.
+pub const MAX_WCIF_SIZE: usize = 16 * 1024 * 1024;
+
+/// Competition ids of the wcif files in the directory, which are named `<competition id>.json`.
+pub fn competitions(dir: &str) -> Vec<String> {
+	let entries = match read_dir(dir) {
+		Ok(entries) => entries,
+		Err(e) => {
+			log::error!("Could not read wcif_dir {dir}: {e}");
+			return Vec::new();
+		}
+	};
+	let mut competitions: Vec<String> = entries
+		.filter_map(|entry| {
+			let path = entry.ok()?.path();
+			if path.extension()? != "json" {
+				return None;
+			}
+			Some(path.file_stem()?.to_str()?.to_owned())
+		})
+		.filter(|id| wcif::valid_competition_id(id))
+		.collect();
+	competitions.sort();
+	competitions
+}
+
+/// Reads the wcif file of a competition from the directory.
+pub fn load(dir: &str, competition: &str) -> Result<WcifContainer, Error> {
+	if !wcif::valid_competition_id(competition) {
+		return Err(Error::CompetitionNotFound(competition.to_owned()));
+	}
+	let data = read(format!("{dir}/{competition}.json"))
+		.map_err(|_| Error::CompetitionNotFound(competition.to_owned()))?;
+	let (_, wcif) = parse(&data)?;
+	Ok(wcif)
+}
+
+/// Reads an uploaded wcif file and returns it with its competition id.
+pub fn parse(data: &[u8]) -> Result<(String, WcifContainer), Error> {
+	let invalid = |e: String| Error::BadRequest(format!("The file is not a valid wcif: {e}"));
+	let json = serde_json::from_slice(data).map_err(|e| invalid(e.to_string()))?;
+	let wcif = wcif::from_value(json).map_err(invalid)?;
+	let id = wcif.get().id.clone();
+	if !wcif::valid_competition_id(&id) {
+		return Err(invalid(format!("{id} is not a competition id")));
+	}
+	Ok((id, wcif))
+}
@@ -0,0 +1,102 @@
+use std::{fs::metadata, sync::Arc, time::{Duration, SystemTime}};
+
+use actix_web::web::Data;
+use tokio::time::interval;
+
+use crate::{db::DB, logging, tls::ReloadableCertificate, Config};
+
+/// How often the watched files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+fn modified(path: &str) -> Option<SystemTime> {
+	metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Reloads the config file and the TLS certificate when their files change or the process receives SIGHUP.
+/// Runs forever.
+pub async fn watch(config_path: String, db: Data<DB>, certificate: Option<Arc<ReloadableCertificate>>) {
+	let config = db.config();
+	let pem_paths = config.public_pem_path.clone().zip(config.private_pem_path.clone());
+	let pem_modified = |(public, private): &(String, String)| (modified(public), modified(private));
+	let mut config_modified = modified(&config_path);
+	let mut certificate_modified = pem_paths.as_ref().map(pem_modified);
+	#[cfg(unix)]
+	let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+		.map_err(|e| log::error!("Could not listen for SIGHUP: {e}"))
+		.ok();
+	let mut interval = interval(POLL_INTERVAL);
+	loop {
+		#[cfg(unix)]
+		let signalled = tokio::select! {
+			_ = interval.tick() => false,
+			Some(_) = async { hangup.as_mut()?.recv().await } => true,
+		};
+		#[cfg(not(unix))]
+		let signalled = {
+			interval.tick().await;
+			false
+		};
+		if signalled {
+			log::info!("Received SIGHUP, reloading config and certificate");
+		}
+
+		let now_modified = modified(&config_path);
+		if signalled || now_modified != config_modified {
+			config_modified = now_modified;
+			reload_config(&config_path, &db);
+		}
+
+		if let (Some(certificate), Some(paths)) = (&certificate, &pem_paths) {
+			let now_modified = pem_modified(paths);
+			if signalled || Some(now_modified) != certificate_modified {
+				certificate_modified = Some(now_modified);
+				match certificate.reload(&paths.0, &paths.1) {
+					Ok(()) => log::info!("Reloaded TLS certificate"),
+					Err(e) => log::error!("Keeping the old TLS certificate: {e}"),
+				}
+			}
+		}
+	}
+}
+
+/// Applies the config file to the running server. Fields which are only read at startup keep their old value.
+fn reload_config(config_path: &str, db: &DB) {
+	let mut new = match Config::load(config_path) {
+		Ok(config) => config,
+		Err(e) => {
+			log::error!("Keeping the old config: {e}");
+			return;
+		}
+	};
+	let old = db.config();
+	let structural = [
+		("bind", old.bind != new.bind),
+		("workers", old.workers != new.workers),
+		("public_pem_path", old.public_pem_path != new.public_pem_path),
+		("private_pem_path", old.private_pem_path != new.private_pem_path),
+		("session_path", old.session_path != new.session_path),
+		("session_key", old.session_key != new.session_key),
+		("snapshot_dir", old.snapshot_dir != new.snapshot_dir),
+		("log.path", old.log.path != new.log.path),
+		("log.json", old.log.json != new.log.json),
+		("log.max_size", old.log.max_size != new.log.max_size),
+		("log.max_files", old.log.max_files != new.log.max_files),
+	];
+	for (field, _) in structural.iter().filter(|(_, changed)| *changed) {
+		log::warn!("Changing {field} requires a restart");
+	}
+	new.bind = old.bind.clone();
+	new.workers = old.workers;
+	new.public_pem_path = old.public_pem_path.clone();
+	new.private_pem_path = old.private_pem_path.clone();
+	new.session_path = old.session_path.clone();
+	new.session_key = old.session_key.clone();
+	new.snapshot_dir = old.snapshot_dir.clone();
+	new.log = logging::LogConfig {
+		level: new.log.level,
+		..old.log.clone()
+	};
+	logging::set_level(new.log.level);
+	db.set_config(new);
+	log::info!("Reloaded config");
+}
@@ -0,0 +1,118 @@
+use std::collections::{HashMap, HashSet};
+
+/// Number of stations one scrambler is expected to keep up with.
+const STATIONS_PER_SCRAMBLER: u64 = 5;
+/// Number of stations one runner is expected to keep up with.
+const STATIONS_PER_RUNNER: u64 = 6;
+
+/// Staff of a single group.
+#[derive(Default, Debug)]
+pub struct GroupStaff {
+	pub judges: Vec<u64>,
+	pub scramblers: Vec<u64>,
+	pub runners: Vec<u64>,
+}
+
+/// Assigns judges, scramblers and runners to every group from the competitors of the round who are
+/// not competing in that group. Work is spread evenly, judges and runners are preferably taken from
+/// the group that just finished and scramblers are preferably the fastest competitors.
+pub fn assign_staff(
+	groups: &[Vec<u64>],
+	stages: u64,
+	stations: u64,
+	seperate_stages: bool,
+	rankings: &HashMap<u64, u64>,
+) -> Vec<GroupStaff> {
+	let no_of_groups = groups.len();
+	let group_of: HashMap<u64, usize> = groups
+		.iter()
+		.enumerate()
+		.flat_map(|(group, members)| members.iter().map(move |id| (*id, group)))
+		.collect();
+	let stages_in_use = if seperate_stages { 1 } else { stages };
+	let mut load: HashMap<u64, u64> = HashMap::new();
+	groups
+		.iter()
+		.enumerate()
+		.map(|(group, members)| {
+			let judges = (members.len() as u64).min(stages_in_use * stations);
+			let scramblers = stages_in_use * stations.div_ceil(STATIONS_PER_SCRAMBLER);
+			let runners = stages_in_use * stations.div_ceil(STATIONS_PER_RUNNER);
+			let mut taken = HashSet::new();
+			let mut pick = |count: u64, load: &mut HashMap<u64, u64>, by_speed: bool| {
+				let mut pool: Vec<u64> = group_of
+					.iter()
+					.filter(|(id, g)| **g != group && !taken.contains(*id))
+					.map(|(id, _)| *id)
+					.collect();
+				pool.sort_by_key(|id| {
+					let secondary = if by_speed {
+						rankings.get(id).copied().unwrap_or(u64::MAX)
+					} else {
+						// The group before this one has distance 1.
+						((group + no_of_groups - group_of[id]) % no_of_groups) as u64
+					};
+					(load.get(id).copied().unwrap_or(0), secondary, *id)
+				});
+				pool.truncate(count as usize);
+				for id in &pool {
+					taken.insert(*id);
+					*load.entry(*id).or_default() += 1;
+				}
+				pool
+			};
+			let scramblers = pick(scramblers, &mut load, true);
+			let runners = pick(runners, &mut load, false);
+			let judges = pick(judges, &mut load, false);
+			GroupStaff {
+				judges,
+				scramblers,
+				runners,
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn a_single_group_has_nobody_to_staff_it() {
+		let staff = assign_staff(&[vec![1, 2, 3]], 1, 3, false, &HashMap::new());
+		assert_eq!(staff.len(), 1);
+		assert!(staff[0].judges.is_empty() && staff[0].scramblers.is_empty() && staff[0].runners.is_empty());
+	}
+
+	#[test]
+	fn groups_are_staffed_by_competitors_of_other_groups() {
+		let groups = [vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]];
+		let staff = assign_staff(&groups, 1, 3, false, &HashMap::new());
+		for (members, staff) in groups.iter().zip(&staff) {
+			let all: Vec<u64> = [&staff.judges, &staff.scramblers, &staff.runners].into_iter().flatten().copied().collect();
+			assert_eq!(all.len(), 5);
+			assert!(all.iter().all(|id| !members.contains(id)));
+			assert_eq!(all.iter().collect::<HashSet<_>>().len(), all.len(), "Nobody has two jobs in a group");
+		}
+	}
+
+	#[test]
+	fn work_is_spread_evenly() {
+		let groups = [vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]];
+		let staff = assign_staff(&groups, 1, 3, false, &HashMap::new());
+		let mut load: HashMap<u64, u64> = HashMap::new();
+		for id in staff.iter().flat_map(|staff| [&staff.judges, &staff.scramblers, &staff.runners]).flatten() {
+			*load.entry(*id).or_default() += 1;
+		}
+		let (min, max) = (load.values().min().unwrap(), load.values().max().unwrap());
+		assert!(max - min <= 1, "{load:?}");
+	}
+
+	#[test]
+	fn the_fastest_free_competitors_scramble() {
+		let rankings: HashMap<u64, u64> = (1..=6).map(|id| (id, 6 - id)).collect();
+		let staff = assign_staff(&[vec![1, 2, 3], vec![4, 5, 6]], 1, 3, false, &rankings);
+		assert_eq!(staff[0].scramblers, [6]);
+		assert_eq!(staff[1].scramblers, [3]);
+	}
+}
@@ -0,0 +1,86 @@
+use std::{
+	collections::HashMap,
+	fs::{read, rename, write},
+	io::ErrorKind,
+};
+
+use ring::{
+	aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+	rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::RoundKey;
+
+/// What is kept of a session across restarts. Cached wcifs are downloaded again when needed, except for offline
+/// sessions whose wcifs only exist here. The WCA login is not kept: the user logs in again and the session keeps its
+/// drafts.
+#[derive(Serialize, Deserialize)]
+pub struct StoredSession {
+	/// Seconds since the unix epoch.
+	pub last_active: u64,
+	pub drafts: Vec<(RoundKey, Vec<Vec<u64>>)>,
+	pub csrf_token: String,
+	pub offline: bool,
+	/// The wcifs of an offline session by competition id. Empty for other sessions.
+	pub wcifs: Vec<(String, Value)>,
+}
+
+/// File holding all sessions, encrypted with ChaCha20-Poly1305 so the personal data of the offline wcifs is not
+/// readable from disk.
+/// The file is the random nonce followed by the encrypted json.
+pub struct SessionStore {
+	path: String,
+	key: LessSafeKey,
+	random: SystemRandom,
+}
+
+impl SessionStore {
+	/// `key` is the hex encoding of a 32 byte key.
+	pub fn new(path: String, key: &str) -> Result<SessionStore, String> {
+		let key = hex::decode(key.trim()).map_err(|e| format!("Session key is not valid hex: {e}"))?;
+		let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+			.map_err(|_| format!("Session key must be {} bytes", CHACHA20_POLY1305.key_len()))?;
+		Ok(SessionStore {
+			path,
+			key: LessSafeKey::new(key),
+			random: SystemRandom::new(),
+		})
+	}
+
+	/// Reads the stored sessions. A missing file means no sessions.
+	pub fn load(&self) -> Result<HashMap<String, StoredSession>, String> {
+		let mut data = match read(&self.path) {
+			Ok(data) => data,
+			Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+			Err(e) => return Err(format!("Could not read {}: {e}", self.path)),
+		};
+		if data.len() < NONCE_LEN {
+			return Err(format!("{} is truncated", self.path));
+		}
+		let (nonce, encrypted) = data.split_at_mut(NONCE_LEN);
+		let nonce = Nonce::try_assume_unique_for_key(nonce).expect("Nonce has the right length");
+		let json = self
+			.key
+			.open_in_place(nonce, Aad::empty(), encrypted)
+			.map_err(|_| format!("Could not decrypt {}, has the session key changed?", self.path))?;
+		serde_json::from_slice(json).map_err(|e| format!("{} is not a valid session file: {e}", self.path))
+	}
+
+	/// Replaces the stored sessions. The file is written next to the old one and moved over it, so a crash never
+	/// leaves a half written file.
+	pub fn save(&self, sessions: &HashMap<String, StoredSession>) -> Result<(), String> {
+		let mut nonce = [0; NONCE_LEN];
+		self.random
+			.fill(&mut nonce)
+			.map_err(|_| "Could not generate nonce".to_string())?;
+		let mut data = serde_json::to_vec(sessions).expect("Sessions serialize to json");
+		self.key
+			.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+			.map_err(|_| "Could not encrypt sessions".to_string())?;
+		let tmp = format!("{}.tmp", self.path);
+		write(&tmp, [&nonce[..], &data].concat()).map_err(|e| format!("Could not write {tmp}: {e}"))?;
+		rename(&tmp, &self.path).map_err(|e| format!("Could not write {}: {e}", self.path))
+	}
+}
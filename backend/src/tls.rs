@@ -0,0 +1,80 @@
+use std::{
+	fs::File,
+	io::BufReader,
+	sync::{Arc, RwLock},
+};
+
+use rustls::{
+	server::{ClientHello, ResolvesServerCert},
+	sign::{any_supported_type, CertifiedKey},
+	Certificate, PrivateKey, ServerConfig,
+};
+use rustls_pemfile::{certs, read_all, Item};
+
+/// Reads every certificate of a pem file, so intermediate certificates are sent along with the leaf certificate.
+pub fn load_certificates(path: &str) -> Result<Vec<Certificate>, String> {
+	let file = File::open(path).map_err(|e| format!("Could not open certificate file {path}: {e}"))?;
+	let chain = certs(&mut BufReader::new(file))
+		.map_err(|e| format!("Could not parse certificate file {path}: {e}"))?;
+	if chain.is_empty() {
+		return Err(format!("Certificate file {path} does not contain any certificates"));
+	}
+	Ok(chain.into_iter().map(Certificate).collect())
+}
+
+/// Reads the first private key of a pem file. PKCS#1 (`RSA PRIVATE KEY`), PKCS#8 (`PRIVATE KEY`) and SEC1
+/// (`EC PRIVATE KEY`) keys are supported.
+pub fn load_private_key(path: &str) -> Result<PrivateKey, String> {
+	let file = File::open(path).map_err(|e| format!("Could not open private key file {path}: {e}"))?;
+	let items = read_all(&mut BufReader::new(file))
+		.map_err(|e| format!("Could not parse private key file {path}: {e}"))?;
+	items
+		.into_iter()
+		.find_map(|item| match item {
+			Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+			_ => None,
+		})
+		.ok_or_else(|| format!("Private key file {path} does not contain a PKCS#1, PKCS#8 or SEC1 private key"))
+}
+
+fn certified_key(public_pem_path: &str, private_pem_path: &str) -> Result<CertifiedKey, String> {
+	let chain = load_certificates(public_pem_path)?;
+	let key = load_private_key(private_pem_path)?;
+	let key = any_supported_type(&key)
+		.map_err(|_| format!("Private key in {private_pem_path} is not a supported RSA, ECDSA or Ed25519 key"))?;
+	Ok(CertifiedKey::new(chain, key))
+}
+
+/// Certificate which can be swapped while the server is running. New connections get the new certificate while
+/// open connections keep going.
+pub struct ReloadableCertificate {
+	current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadableCertificate {
+	pub fn load(public_pem_path: &str, private_pem_path: &str) -> Result<ReloadableCertificate, String> {
+		Ok(ReloadableCertificate {
+			current: RwLock::new(Arc::new(certified_key(public_pem_path, private_pem_path)?)),
+		})
+	}
+
+	/// Loads the certificate again. The old certificate stays in use if the new one can not be loaded.
+	pub fn reload(&self, public_pem_path: &str, private_pem_path: &str) -> Result<(), String> {
+		let key = certified_key(public_pem_path, private_pem_path)?;
+		*self.current.write().unwrap() = Arc::new(key);
+		Ok(())
+	}
+}
+
+impl ResolvesServerCert for ReloadableCertificate {
+	fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
+		Some(self.current.read().unwrap().clone())
+	}
+}
+
+pub fn server_config(certificate: Arc<ReloadableCertificate>) -> ServerConfig {
+	ServerConfig::builder()
+		.with_safe_defaults()
+		.with_no_client_auth()
+		.with_cert_resolver(certificate)
+}
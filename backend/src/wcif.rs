@@ -0,0 +1,981 @@
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+	fmt::{self, Display, Formatter},
+};
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use serde_json::{json, Value};
+use wca_oauth::WcifContainer;
+
+use common::Constraints;
+
+use crate::{error::Error, staff::GroupStaff};
+
+const CONSTRAINTS_EXTENSION: &str = "dve.GroupingConstraints";
+
+/// Events where the single is the primary result and therefore the better predictor of speed.
+const SINGLE_FIRST: [&str; 4] = ["333bf", "444bf", "555bf", "333mbf"];
+
+pub fn to_json(wcif: &WcifContainer) -> Value {
+	serde_json::to_value(wcif.get()).expect("Wcif is always representable as json")
+}
+
+pub fn copy(wcif: &WcifContainer) -> WcifContainer {
+	from_value(to_json(wcif)).expect("Wcif is always readable from its own json")
+}
+
+/// Competition ids only contain letters and digits, which also keeps them from escaping a directory when used in a
+/// file name.
+pub fn valid_competition_id(id: &str) -> bool {
+	!id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Reads a wcif which did not come from the WCA API, such as an uploaded file.
+pub fn from_value(json: Value) -> Result<WcifContainer, String> {
+	serde_json::from_value(json).map(WcifContainer::new).map_err(|e| e.to_string())
+}
+
+fn from_json(wcif: &mut WcifContainer, json: Value) {
+	*wcif.get_mut() = serde_json::from_value(json).expect("Modified wcif is still a valid wcif");
+}
+
+/// Checks that the event has the round, as generating groups for a missing round panics.
+pub fn check_round(wcif: &WcifContainer, event: &str, round: usize) -> Result<(), Error> {
+	let round_id = format!("{event}-r{round}");
+	if wcif.round_iter().any(|r| r.id == round_id) {
+		Ok(())
+	} else {
+		Err(Error::RoundNotFound(round_id))
+	}
+}
+
+/// Computes the seed of every competitor for a round, 0 being the fastest. The first round is
+/// seeded by personal records, later rounds by the ranking in the previous round. Competitors
+/// without a personal record or result are not included.
+pub fn rankings(wcif: &WcifContainer, event: &str, round: usize) -> HashMap<u64, u64> {
+	let json = to_json(wcif);
+	let mut keys: Vec<(u64, (u64, u64))> = if round <= 1 {
+		personal_best_keys(&json, event)
+	} else {
+		previous_round_keys(&json, event, round - 1)
+	};
+	keys.sort_by_key(|(_, key)| *key);
+	keys.into_iter()
+		.enumerate()
+		.map(|(seed, (id, _))| (id, seed as u64))
+		.collect()
+}
+
+fn personal_best_keys(json: &Value, event: &str) -> Vec<(u64, (u64, u64))> {
+	let (primary, secondary) = if SINGLE_FIRST.contains(&event) {
+		("single", "average")
+	} else {
+		("average", "single")
+	};
+	persons(json)
+		.filter_map(|person| {
+			let id = person.get("registrantId")?.as_u64()?;
+			let world_ranking = |kind: &str| {
+				person
+					.get("personalBests")?
+					.as_array()?
+					.iter()
+					.find(|pb| {
+						pb.get("eventId").and_then(Value::as_str) == Some(event)
+							&& pb.get("type").and_then(Value::as_str) == Some(kind)
+					})?
+					.get("worldRanking")?
+					.as_u64()
+			};
+			match world_ranking(primary) {
+				Some(ranking) => Some((id, (0, ranking))),
+				None => Some((id, (1, world_ranking(secondary)?))),
+			}
+		})
+		.collect()
+}
+
+fn previous_round_keys(json: &Value, event: &str, round: usize) -> Vec<(u64, (u64, u64))> {
+	let round_id = format!("{event}-r{round}");
+	json.get("events")
+		.and_then(Value::as_array)
+		.into_iter()
+		.flatten()
+		.filter_map(|e| e.get("rounds")?.as_array())
+		.flatten()
+		.filter(|r| r.get("id").and_then(Value::as_str) == Some(&round_id))
+		.filter_map(|r| r.get("results")?.as_array())
+		.flatten()
+		.filter_map(|result| {
+			let id = result.get("personId")?.as_u64()?;
+			let ranking = result.get("ranking")?.as_u64()?;
+			Some((id, (0, ranking)))
+		})
+		.collect()
+}
+
+fn persons(json: &Value) -> impl Iterator<Item = &Value> {
+	json.get("persons")
+		.and_then(Value::as_array)
+		.into_iter()
+		.flatten()
+}
+
+/// Time window of a round as unix timestamps, spanning every room the round is held in.
+pub fn round_window(wcif: &WcifContainer, event: &str, round: usize) -> Option<(i64, i64)> {
+	let json = to_json(wcif);
+	let round_code = format!("{event}-r{round}");
+	activities(&json)
+		.into_iter()
+		.filter(|activity| {
+			activity.get("activityCode").and_then(Value::as_str) == Some(&round_code)
+		})
+		.filter_map(activity_window)
+		.reduce(|(start, end), (s, e)| (start.min(s), end.max(e)))
+}
+
+/// Time windows of every activity each person is assigned to outside of the given round.
+pub fn busy_windows(
+	wcif: &WcifContainer,
+	event: &str,
+	round: usize,
+) -> HashMap<u64, Vec<(i64, i64)>> {
+	let json = to_json(wcif);
+	let round_code = format!("{event}-r{round}");
+	let in_round = |code: &str| code == round_code || code.starts_with(&format!("{round_code}-"));
+	let windows: HashMap<u64, (i64, i64)> = activities(&json)
+		.into_iter()
+		.filter(|activity| {
+			!activity
+				.get("activityCode")
+				.and_then(Value::as_str)
+				.is_some_and(in_round)
+		})
+		.filter_map(|activity| Some((activity.get("id")?.as_u64()?, activity_window(activity)?)))
+		.collect();
+	persons(&json)
+		.filter_map(|person| {
+			let id = person.get("registrantId")?.as_u64()?;
+			let busy: Vec<_> = person
+				.get("assignments")?
+				.as_array()?
+				.iter()
+				.filter_map(|assignment| {
+					windows
+						.get(&assignment.get("activityId")?.as_u64()?)
+						.copied()
+				})
+				.collect();
+			Some((id, busy))
+		})
+		.filter(|(_, busy)| !busy.is_empty())
+		.collect()
+}
+
+fn activity_window(activity: &Value) -> Option<(i64, i64)> {
+	let time = |key: &str| {
+		let time = activity.get(key)?.as_str()?;
+		Some(DateTime::parse_from_rfc3339(time).ok()?.timestamp())
+	};
+	Some((time("startTime")?, time("endTime")?))
+}
+
+/// The activities of every room, without their child activities.
+fn top_level_activities_mut(json: &mut Value) -> impl Iterator<Item = &mut Value> {
+	json.get_mut("schedule")
+		.and_then(|schedule| schedule.get_mut("venues"))
+		.and_then(Value::as_array_mut)
+		.into_iter()
+		.flatten()
+		.filter_map(|venue| venue.get_mut("rooms")?.as_array_mut())
+		.flatten()
+		.filter_map(|room| room.get_mut("activities")?.as_array_mut())
+		.flatten()
+}
+
+/// Ids of the child activities of an activity at any depth.
+fn descendant_ids(activity: &Value, out: &mut HashSet<u64>) {
+	for child in activity.get("childActivities").and_then(Value::as_array).into_iter().flatten() {
+		out.extend(child.get("id").and_then(Value::as_u64));
+		descendant_ids(child, out);
+	}
+}
+
+/// All activities of the schedule including child activities.
+fn activities(json: &Value) -> Vec<&Value> {
+	fn collect<'a>(activities: &'a Value, out: &mut Vec<&'a Value>) {
+		for activity in activities.as_array().into_iter().flatten() {
+			out.push(activity);
+			if let Some(children) = activity.get("childActivities") {
+				collect(children, out);
+			}
+		}
+	}
+	let mut out = Vec::new();
+	let rooms = json
+		.get("schedule")
+		.and_then(|schedule| schedule.get("venues"))
+		.and_then(Value::as_array)
+		.into_iter()
+		.flatten()
+		.filter_map(|venue| venue.get("rooms")?.as_array())
+		.flatten();
+	for room in rooms {
+		if let Some(activities) = room.get("activities") {
+			collect(activities, &mut out);
+		}
+	}
+	out
+}
+
+/// Replaces the staff assignments of the groups of a round. The groups have to exist as child
+/// activities, which is the case after the groups have been patched. If the round is held in several
+/// rooms, the staff of a group is split between the rooms.
+pub fn set_staff_assignments(
+	wcif: &mut WcifContainer,
+	event: &str,
+	round: usize,
+	staff: &[GroupStaff],
+) {
+	let mut json = to_json(wcif);
+	let group_activities: Vec<Vec<u64>> = {
+		let activities = activities(&json);
+		(1..=staff.len())
+			.map(|group| {
+				let code = format!("{event}-r{round}-g{group}");
+				activities
+					.iter()
+					.filter(|activity| {
+						activity.get("activityCode").and_then(Value::as_str) == Some(&code)
+					})
+					.filter_map(|activity| activity.get("id")?.as_u64())
+					.collect()
+			})
+			.collect()
+	};
+	let round_activities: HashSet<u64> = group_activities.iter().flatten().copied().collect();
+	let mut new: HashMap<u64, Vec<Value>> = HashMap::new();
+	for (staff, activities) in staff.iter().zip(&group_activities) {
+		if activities.is_empty() {
+			continue;
+		}
+		let roles = [
+			("staff-judge", &staff.judges),
+			("staff-scrambler", &staff.scramblers),
+			("staff-runner", &staff.runners),
+		];
+		for (code, ids) in roles {
+			for (id, activity) in ids.iter().zip(activities.iter().cycle()) {
+				new.entry(*id).or_default().push(json!({
+					"activityId": activity,
+					"assignmentCode": code,
+					"stationNumber": null,
+				}));
+			}
+		}
+	}
+	let persons = json.get_mut("persons").and_then(Value::as_array_mut);
+	for person in persons.into_iter().flatten() {
+		let Some(id) = person.get("registrantId").and_then(Value::as_u64) else {
+			continue;
+		};
+		let Some(assignments) = person.get_mut("assignments").and_then(Value::as_array_mut) else {
+			continue;
+		};
+		assignments.retain(|assignment| {
+			let old_staff = assignment
+				.get("assignmentCode")
+				.and_then(Value::as_str)
+				.is_some_and(|code| code.starts_with("staff-"));
+			let in_round = assignment
+				.get("activityId")
+				.and_then(Value::as_u64)
+				.is_some_and(|activity| round_activities.contains(&activity));
+			!(old_staff && in_round)
+		});
+		assignments.extend(new.remove(&id).unwrap_or_default());
+	}
+	from_json(wcif, json);
+}
+
+/// Writes the groups of a round as child activities and competitor assignments, the way patching the groups to the
+/// WCA website does. Used offline, where nothing is patched. The groups split the time of the first room the round
+/// is held in, and group activities which already exist keep their id.
+pub fn set_groups(wcif: &mut WcifContainer, event: &str, round: usize, groups: &[Vec<u64>]) {
+	let mut json = to_json(wcif);
+	let round_code = format!("{event}-r{round}");
+	let group_prefix = format!("{round_code}-g");
+	let is_group = |activity: &Value| {
+		activity
+			.get("activityCode")
+			.and_then(Value::as_str)
+			.is_some_and(|code| code.starts_with(&group_prefix))
+	};
+	let (old_groups, mut next_id) = {
+		let activities = activities(&json);
+		let old_groups: HashMap<String, u64> = activities
+			.iter()
+			.filter(|activity| is_group(activity))
+			.filter_map(|activity| {
+				Some((activity.get("activityCode")?.as_str()?.to_owned(), activity.get("id")?.as_u64()?))
+			})
+			.collect();
+		let max_id = activities.iter().filter_map(|activity| activity.get("id")?.as_u64()).max();
+		(old_groups, max_id.unwrap_or(0) + 1)
+	};
+
+	let mut group_ids = Vec::new();
+	let round_activities = top_level_activities_mut(&mut json)
+		.filter(|activity| activity.get("activityCode").and_then(Value::as_str) == Some(&round_code));
+	for activity in round_activities {
+		let window = activity_window(activity);
+		let name = activity.get("name").and_then(Value::as_str).unwrap_or(&round_code).to_owned();
+		let Some(children) = activity.get_mut("childActivities").and_then(Value::as_array_mut) else {
+			continue;
+		};
+		children.retain(|child| !is_group(child));
+		let Some((start, end)) = window.filter(|_| group_ids.is_empty()) else {
+			continue;
+		};
+		let time = |at: i64| Utc.timestamp_opt(at, 0).unwrap().to_rfc3339_opts(SecondsFormat::Secs, true);
+		let count = groups.len() as i64;
+		for group in 1..=groups.len() {
+			let code = format!("{group_prefix}{group}");
+			let id = old_groups.get(&code).copied().unwrap_or_else(|| {
+				next_id += 1;
+				next_id - 1
+			});
+			let offset = |group: i64| start + (end - start) * group / count;
+			children.push(json!({
+				"id": id,
+				"name": format!("{name}, Group {group}"),
+				"activityCode": code,
+				"startTime": time(offset(group as i64 - 1)),
+				"endTime": time(offset(group as i64)),
+				"childActivities": [],
+				"scrambleSetId": null,
+				"extensions": [],
+			}));
+			group_ids.push(id);
+		}
+	}
+
+	// Staff assignments stay with groups which still exist. Competitors are assigned again from scratch.
+	let old_ids: HashSet<u64> = old_groups.values().copied().collect();
+	let mut new: HashMap<u64, Value> = HashMap::new();
+	for (group, activity) in groups.iter().zip(&group_ids) {
+		for (station, id) in group.iter().enumerate() {
+			new.insert(*id, json!({
+				"activityId": activity,
+				"assignmentCode": "competitor",
+				"stationNumber": station + 1,
+			}));
+		}
+	}
+	let persons = json.get_mut("persons").and_then(Value::as_array_mut);
+	for person in persons.into_iter().flatten() {
+		let Some(id) = person.get("registrantId").and_then(Value::as_u64) else {
+			continue;
+		};
+		let Some(assignments) = person.get_mut("assignments").and_then(Value::as_array_mut) else {
+			continue;
+		};
+		assignments.retain(|assignment| {
+			let Some(activity) = assignment.get("activityId").and_then(Value::as_u64) else {
+				return true;
+			};
+			let competitor = assignment.get("assignmentCode").and_then(Value::as_str) == Some("competitor");
+			!old_ids.contains(&activity) || (!competitor && group_ids.contains(&activity))
+		});
+		assignments.extend(new.remove(&id));
+	}
+	from_json(wcif, json);
+}
+
+/// Puts back the groups of an earlier version of the wcif: the child activities of every activity which existed back
+/// then and the assignments to them. Everything else stays as it is now. Activities and persons are matched by id.
+pub fn restore_groups(wcif: &mut WcifContainer, snapshot: &WcifContainer) {
+	let mut old = to_json(snapshot);
+	let mut json = to_json(wcif);
+	let old_children: HashMap<u64, Value> = top_level_activities_mut(&mut old)
+		.filter_map(|activity| Some((activity.get("id")?.as_u64()?, activity.get("childActivities")?.clone())))
+		.collect();
+
+	let mut removed = HashSet::new();
+	let mut restored = HashSet::new();
+	for activity in top_level_activities_mut(&mut json) {
+		let Some(children) = activity.get("id").and_then(Value::as_u64).and_then(|id| old_children.get(&id)) else {
+			continue;
+		};
+		descendant_ids(activity, &mut removed);
+		activity["childActivities"] = children.clone();
+		descendant_ids(activity, &mut restored);
+	}
+
+	let old_assignments: HashMap<u64, Vec<Value>> = persons(&old)
+		.filter_map(|person| {
+			let id = person.get("registrantId")?.as_u64()?;
+			let assignments = person
+				.get("assignments")?
+				.as_array()?
+				.iter()
+				.filter(|assignment| {
+					assignment.get("activityId").and_then(Value::as_u64).is_some_and(|id| restored.contains(&id))
+				})
+				.cloned()
+				.collect();
+			Some((id, assignments))
+		})
+		.collect();
+	let persons = json.get_mut("persons").and_then(Value::as_array_mut);
+	for person in persons.into_iter().flatten() {
+		let Some(id) = person.get("registrantId").and_then(Value::as_u64) else {
+			continue;
+		};
+		let Some(assignments) = person.get_mut("assignments").and_then(Value::as_array_mut) else {
+			continue;
+		};
+		assignments.retain(|assignment| {
+			let activity = assignment.get("activityId").and_then(Value::as_u64);
+			!activity.is_some_and(|activity| removed.contains(&activity) || restored.contains(&activity))
+		});
+		assignments.extend(old_assignments.get(&id).into_iter().flatten().cloned());
+	}
+	from_json(wcif, json);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+	Added,
+	Changed,
+	Removed,
+}
+
+/// Changes of activities and assignments between two versions of a wcif, described for people.
+pub struct WcifDiff {
+	pub activities: Vec<(Change, String)>,
+	/// Changed assignments by person name, sorted by name.
+	pub persons: Vec<(String, Vec<(Change, String)>)>,
+}
+
+impl WcifDiff {
+	pub fn is_empty(&self) -> bool {
+		self.activities.is_empty() && self.persons.is_empty()
+	}
+}
+
+impl Display for WcifDiff {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let describe = |changes: &[(Change, String)]| {
+			changes.iter().map(|(change, text)| format!("{} {text}", change.sign())).collect::<Vec<_>>().join(", ")
+		};
+		let mut parts = Vec::new();
+		if !self.activities.is_empty() {
+			parts.push(format!("Activities: {}", describe(&self.activities)));
+		}
+		parts.extend(self.persons.iter().map(|(name, changes)| format!("{name}: {}", describe(changes))));
+		write!(f, "{}", parts.join("; "))
+	}
+}
+
+impl Change {
+	pub fn sign(self) -> &'static str {
+		match self {
+			Change::Added => "+",
+			Change::Changed => "~",
+			Change::Removed => "-",
+		}
+	}
+}
+
+/// Compares the activities and assignments of two versions of a wcif. Activities are matched by activity code, so
+/// an activity which got a new id is not a change.
+pub fn diff(before: &WcifContainer, after: &WcifContainer) -> WcifDiff {
+	diff_where(before, after, |_| true)
+}
+
+/// Like [`diff`], but only the changes of activities and assignments of the rounds, like `333-r1`.
+pub fn round_diff(before: &WcifContainer, after: &WcifContainer, rounds: &[String]) -> WcifDiff {
+	diff_where(before, after, |round| rounds.iter().any(|other| other == round))
+}
+
+fn diff_where(before: &WcifContainer, after: &WcifContainer, keep: impl Fn(&str) -> bool) -> WcifDiff {
+	let before = to_json(before);
+	let after = to_json(after);
+	let before_activities = activity_codes(&before);
+	let after_activities = activity_codes(&after);
+
+	let codes: BTreeSet<&String> = before_activities.values().chain(after_activities.values()).map(|(code, _)| code).collect();
+	let window_of = |activities: &Activities, code: &str| {
+		activities.values().find(|(other, _)| other == code).map(|(_, window)| *window)
+	};
+	let activities = codes
+		.into_iter()
+		.filter(|code| keep(round_of(code)))
+		.filter_map(|code| match (window_of(&before_activities, code), window_of(&after_activities, code)) {
+			(None, Some(window)) => Some((Change::Added, format!("{code} {}", describe_window(window)))),
+			(Some(window), None) => Some((Change::Removed, format!("{code} {}", describe_window(window)))),
+			(Some(old), Some(new)) if old != new => Some((
+				Change::Changed,
+				format!("{code} from {} to {}", describe_window(old), describe_window(new)),
+			)),
+			_ => None,
+		})
+		.collect();
+
+	let before_assignments = assignments(&before, &before_activities);
+	let after_assignments = assignments(&after, &after_activities);
+	let ids: BTreeSet<u64> = before_assignments.keys().chain(after_assignments.keys()).copied().collect();
+	let mut persons: Vec<(String, Vec<(Change, String)>)> = ids
+		.into_iter()
+		.filter_map(|id| {
+			let (name, old) = before_assignments.get(&id).cloned().unwrap_or_default();
+			let (new_name, new) = after_assignments.get(&id).cloned().unwrap_or_default();
+			let keys: BTreeSet<&(String, String)> = old.keys().chain(new.keys()).filter(|(_, round)| keep(round)).collect();
+			let mut changes = Vec::new();
+			for key @ (role, _) in keys {
+				let old = old.get(key).map(Vec::as_slice).unwrap_or_default();
+				let new = new.get(key).map(Vec::as_slice).unwrap_or_default();
+				match (old, new) {
+					(old, new) if old == new => (),
+					([old], [new]) => changes.push((
+						Change::Changed,
+						format!("{} from {} to {}", role_name(role), describe_assignment(old), describe_assignment(new)),
+					)),
+					(old, new) => {
+						for assignment in old.iter().filter(|a| !new.contains(a)) {
+							changes.push((Change::Removed, format!("{} in {}", role_name(role), describe_assignment(assignment))));
+						}
+						for assignment in new.iter().filter(|a| !old.contains(a)) {
+							changes.push((Change::Added, format!("{} in {}", role_name(role), describe_assignment(assignment))));
+						}
+					}
+				}
+			}
+			let name = if new_name.is_empty() { name } else { new_name };
+			(!changes.is_empty()).then_some((name, changes))
+		})
+		.collect();
+	persons.sort_by(|(a, _), (b, _)| a.cmp(b));
+	WcifDiff { activities, persons }
+}
+
+/// Activity code and time window of every activity by id.
+type Activities = HashMap<u64, (String, Option<(i64, i64)>)>;
+
+fn activity_codes(json: &Value) -> Activities {
+	activities(json)
+		.into_iter()
+		.filter_map(|activity| {
+			let id = activity.get("id")?.as_u64()?;
+			let code = activity.get("activityCode")?.as_str()?.to_owned();
+			Some((id, (code, activity_window(activity))))
+		})
+		.collect()
+}
+
+/// Activity code and station of an assignment.
+type Assignment = (String, Option<u64>);
+
+/// Name of a person and their assignments by assignment code and round.
+type PersonAssignments = (String, BTreeMap<(String, String), Vec<Assignment>>);
+
+/// Assignments of every person by registrant id. Grouping them by round makes moving someone to another group of a
+/// round one change.
+fn assignments(json: &Value, activities: &Activities) -> HashMap<u64, PersonAssignments> {
+	persons(json)
+		.filter_map(|person| {
+			let id = person.get("registrantId")?.as_u64()?;
+			let name = person.get("name").and_then(Value::as_str).unwrap_or_default().to_owned();
+			let mut by_round: BTreeMap<(String, String), Vec<Assignment>> = BTreeMap::new();
+			for assignment in person.get("assignments").and_then(Value::as_array).into_iter().flatten() {
+				let Some((code, _)) = assignment.get("activityId").and_then(Value::as_u64).and_then(|id| activities.get(&id)) else {
+					continue;
+				};
+				let role = assignment.get("assignmentCode").and_then(Value::as_str).unwrap_or_default().to_owned();
+				let round = round_of(code).to_owned();
+				let station = assignment.get("stationNumber").and_then(Value::as_u64);
+				by_round.entry((role, round)).or_default().push((code.clone(), station));
+			}
+			for assignments in by_round.values_mut() {
+				assignments.sort();
+			}
+			Some((id, (name, by_round)))
+		})
+		.collect()
+}
+
+/// Round id and group number of every group activity by activity id.
+pub fn group_activities(wcif: &WcifContainer) -> Vec<(u64, (String, u64))> {
+	activity_codes(&to_json(wcif))
+		.into_iter()
+		.filter_map(|(id, (code, _))| {
+			let (round, group) = code.split_once("-g")?;
+			let group = group.split('-').next()?.parse().ok()?;
+			Some((id, (round.to_owned(), group)))
+		})
+		.collect()
+}
+
+/// The round an activity code belongs to, like `333-r1` for `333-r1-g2`.
+fn round_of(code: &str) -> &str {
+	code.split("-g").next().unwrap_or(code)
+}
+
+fn describe_window(window: Option<(i64, i64)>) -> String {
+	let time = |at: i64| Utc.timestamp_opt(at, 0).single().map_or_else(String::new, |time| time.format("%H:%M").to_string());
+	match window {
+		Some((start, end)) => format!("({}-{} UTC)", time(start), time(end)),
+		None => String::new(),
+	}
+}
+
+fn describe_assignment((code, station): &Assignment) -> String {
+	match station {
+		Some(station) => format!("{code} at station {station}"),
+		None => code.clone(),
+	}
+}
+
+fn role_name(role: &str) -> &str {
+	match role {
+		"competitor" => "Competitor",
+		"staff-judge" => "Judge",
+		"staff-scrambler" => "Scrambler",
+		"staff-runner" => "Runner",
+		other => other,
+	}
+}
+
+/// Keep-together and keep-apart constraints stored in the wcif extension.
+pub fn constraints(wcif: &WcifContainer) -> Constraints {
+	wcif.get()
+		.extensions
+		.iter()
+		.find(|ext| ext.get("id").and_then(Value::as_str) == Some(CONSTRAINTS_EXTENSION))
+		.and_then(|ext| ext.get("data"))
+		.and_then(|data| serde_json::from_value(data.clone()).ok())
+		.unwrap_or_default()
+}
+
+pub fn set_constraints(wcif: &mut WcifContainer, constraints: &Constraints) {
+	let extension = json!({
+		"id": CONSTRAINTS_EXTENSION,
+		"specUrl": "https://github.com/Daniel-Anker-Hermansen/scorecards_server",
+		"data": constraints,
+	});
+	let extensions = &mut wcif.get_mut().extensions;
+	extensions.retain(|ext| ext.get("id").and_then(Value::as_str) != Some(CONSTRAINTS_EXTENSION));
+	extensions.push(extension);
+}
+
+/// Name of the competition, falling back to its id.
+pub fn name(wcif: &WcifContainer) -> String {
+	to_json(wcif).get("name").and_then(Value::as_str).unwrap_or(&wcif.get().id).to_owned()
+}
+
+/// Registrant id and name of every registered person, sorted by name.
+pub fn person_names(wcif: &WcifContainer) -> Vec<(u64, String)> {
+	let json = to_json(wcif);
+	let mut names: Vec<_> = persons(&json)
+		.filter_map(|person| {
+			let id = person.get("registrantId")?.as_u64()?;
+			let name = person.get("name")?.as_str()?;
+			Some((id, name.to_owned()))
+		})
+		.collect();
+	names.sort_by(|(_, a), (_, b)| a.cmp(b));
+	names
+}
+
+/// Registrant ids of persons without a WCA ID.
+pub fn newcomers(wcif: &WcifContainer) -> Vec<u64> {
+	let json = to_json(wcif);
+	persons(&json)
+		.filter(|person| person.get("wcaId").is_none_or(Value::is_null))
+		.filter_map(|person| person.get("registrantId")?.as_u64())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn fixture() -> Value {
+		serde_json::from_str(include_str!("../tests/fixtures/MockOpen2099.json")).unwrap()
+	}
+
+	fn group(id: u64) -> Value {
+		json!({
+			"id": id,
+			"name": "3x3x3 Cube, Round 1, Group 1",
+			"activityCode": "333-r1-g1",
+			"startTime": "2099-06-05T08:00:00Z",
+			"endTime": "2099-06-05T09:00:00Z",
+			"childActivities": [],
+			"extensions": []
+		})
+	}
+
+	#[test]
+	fn staff_is_split_between_the_rooms_of_a_group() {
+		let mut json = fixture();
+		let rooms = &mut json["schedule"]["venues"][0]["rooms"];
+		rooms[0]["activities"][0]["childActivities"] = json!([group(2)]);
+		let mut second_room = rooms[0].clone();
+		second_room["id"] = json!(2);
+		second_room["activities"][0]["id"] = json!(3);
+		second_room["activities"][0]["childActivities"] = json!([group(4)]);
+		rooms.as_array_mut().unwrap().push(second_room);
+		// An old judge assignment in the second room is replaced.
+		json["persons"][3]["assignments"] = json!([{ "activityId": 4, "assignmentCode": "staff-judge", "stationNumber": null }]);
+		let mut wcif = from_value(json).unwrap();
+
+		let staff = GroupStaff { judges: vec![1, 2], ..GroupStaff::default() };
+		set_staff_assignments(&mut wcif, "333", 1, &[staff]);
+
+		let json = to_json(&wcif);
+		let activities = |person: usize| -> Vec<u64> {
+			json["persons"][person]["assignments"]
+				.as_array()
+				.unwrap()
+				.iter()
+				.filter(|assignment| assignment["assignmentCode"] == "staff-judge")
+				.map(|assignment| assignment["activityId"].as_u64().unwrap())
+				.collect()
+		};
+		assert_eq!(activities(0), [2]);
+		assert_eq!(activities(1), [4]);
+		assert!(activities(3).is_empty());
+	}
+
+	#[test]
+	fn first_rounds_are_seeded_by_personal_records() {
+		let mut json = fixture();
+		// Dave only has a single, so he comes after everyone with an average however fast it is.
+		json["persons"][3]["personalBests"] = json!([{ "eventId": "333", "best": 600, "worldRanking": 10, "type": "single" }]);
+		let wcif = from_value(json).unwrap();
+
+		assert!(personal_best_keys(&to_json(&wcif), "333").contains(&(4, (1, 10))));
+		// Carol has no personal record and is left out.
+		assert_eq!(rankings(&wcif, "333", 1), HashMap::from([(2, 0), (1, 1), (4, 2)]));
+	}
+
+	#[test]
+	fn blindfolded_events_are_seeded_by_single() {
+		let mut json = fixture();
+		json["persons"][0]["personalBests"] = json!([{ "eventId": "333bf", "best": 3000, "worldRanking": 100, "type": "single" }]);
+		json["persons"][1]["personalBests"] = json!([
+			{ "eventId": "333bf", "best": 4000, "worldRanking": 200, "type": "single" },
+			{ "eventId": "333bf", "best": 5000, "worldRanking": 50, "type": "average" },
+		]);
+		let wcif = from_value(json).unwrap();
+
+		assert_eq!(rankings(&wcif, "333bf", 1), HashMap::from([(1, 0), (2, 1)]));
+	}
+
+	#[test]
+	fn later_rounds_are_seeded_by_the_previous_round() {
+		let mut json = fixture();
+		json["events"][0]["rounds"][0]["results"] = json!([
+			{ "personId": 1, "ranking": 2, "attempts": [] },
+			{ "personId": 3, "ranking": 1, "attempts": [] },
+		]);
+		let wcif = from_value(json).unwrap();
+
+		assert_eq!(previous_round_keys(&to_json(&wcif), "333", 1), [(1, (0, 2)), (3, (0, 1))]);
+		// Personal records no longer matter, and Bob who has no result is left out.
+		assert_eq!(rankings(&wcif, "333", 2), HashMap::from([(3, 0), (1, 1)]));
+	}
+
+	fn at(time: &str) -> i64 {
+		DateTime::parse_from_rfc3339(time).unwrap().timestamp()
+	}
+
+	#[test]
+	fn round_window_spans_every_room() {
+		let mut json = fixture();
+		let rooms = &mut json["schedule"]["venues"][0]["rooms"];
+		let mut second_room = rooms[0].clone();
+		second_room["id"] = json!(2);
+		second_room["activities"][0]["id"] = json!(2);
+		second_room["activities"][0]["startTime"] = json!("2099-06-05T08:30:00Z");
+		second_room["activities"][0]["endTime"] = json!("2099-06-05T09:30:00Z");
+		rooms.as_array_mut().unwrap().push(second_room);
+		let wcif = from_value(json).unwrap();
+
+		assert_eq!(round_window(&wcif, "333", 1), Some((at("2099-06-05T08:00:00Z"), at("2099-06-05T09:30:00Z"))));
+		assert_eq!(round_window(&wcif, "333", 2), None);
+	}
+
+	#[test]
+	fn busy_windows_leave_out_the_round_itself() {
+		let mut json = fixture();
+		let activities = &mut json["schedule"]["venues"][0]["rooms"][0]["activities"];
+		activities[0]["childActivities"] = json!([group(2)]);
+		activities.as_array_mut().unwrap().push(json!({
+			"id": 3,
+			"name": "2x2x2 Cube, Round 1",
+			"activityCode": "222-r1",
+			"startTime": "2099-06-05T09:00:00Z",
+			"endTime": "2099-06-05T10:00:00Z",
+			"childActivities": [],
+			"extensions": []
+		}));
+		json["persons"][0]["assignments"] = json!([
+			{ "activityId": 2, "assignmentCode": "competitor", "stationNumber": 1 },
+			{ "activityId": 3, "assignmentCode": "staff-judge", "stationNumber": null },
+		]);
+		json["persons"][1]["assignments"] = json!([{ "activityId": 2, "assignmentCode": "staff-judge", "stationNumber": null }]);
+		let wcif = from_value(json).unwrap();
+
+		// Bob is only busy in the round itself, so he is not busy at all.
+		let judging = (at("2099-06-05T09:00:00Z"), at("2099-06-05T10:00:00Z"));
+		assert_eq!(busy_windows(&wcif, "333", 1), HashMap::from([(1, vec![judging])]));
+	}
+
+	/// Activity id and station of the competitor assignments of every person.
+	fn competing(json: &Value) -> Vec<Vec<(u64, u64)>> {
+		persons(json)
+			.map(|person| {
+				person["assignments"]
+					.as_array()
+					.unwrap()
+					.iter()
+					.filter(|assignment| assignment["assignmentCode"] == "competitor")
+					.map(|assignment| (assignment["activityId"].as_u64().unwrap(), assignment["stationNumber"].as_u64().unwrap()))
+					.collect()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn groups_are_written_like_a_patch_would() {
+		let mut wcif = from_value(fixture()).unwrap();
+		set_groups(&mut wcif, "333", 1, &[vec![1, 2], vec![3]]);
+
+		let json = to_json(&wcif);
+		let groups = json["schedule"]["venues"][0]["rooms"][0]["activities"][0]["childActivities"].as_array().unwrap();
+		let described: Vec<_> = groups
+			.iter()
+			.map(|group| (group["id"].as_u64().unwrap(), group["activityCode"].as_str().unwrap(), activity_window(group).unwrap()))
+			.collect();
+		assert_eq!(described, [
+			(2, "333-r1-g1", (at("2099-06-05T08:00:00Z"), at("2099-06-05T08:30:00Z"))),
+			(3, "333-r1-g2", (at("2099-06-05T08:30:00Z"), at("2099-06-05T09:00:00Z"))),
+		]);
+		assert_eq!(competing(&json), [vec![(2, 1)], vec![(2, 2)], vec![(3, 1)], vec![]]);
+	}
+
+	#[test]
+	fn setting_groups_again_keeps_existing_groups_and_their_staff() {
+		let mut wcif = from_value(fixture()).unwrap();
+		set_groups(&mut wcif, "333", 1, &[vec![1, 2], vec![3]]);
+		let mut json = to_json(&wcif);
+		json["persons"][3]["assignments"] = json!([
+			{ "activityId": 2, "assignmentCode": "staff-judge", "stationNumber": null },
+			{ "activityId": 3, "assignmentCode": "staff-judge", "stationNumber": null },
+		]);
+		let mut wcif = from_value(json).unwrap();
+		set_groups(&mut wcif, "333", 1, &[vec![3, 1, 2]]);
+
+		let json = to_json(&wcif);
+		let groups = json["schedule"]["venues"][0]["rooms"][0]["activities"][0]["childActivities"].as_array().unwrap();
+		assert_eq!(groups.len(), 1);
+		assert_eq!(groups[0]["id"], 2);
+		assert_eq!(competing(&json), [vec![(2, 2)], vec![(2, 3)], vec![(2, 1)], vec![]]);
+		// The judge of the group which is gone loses that assignment.
+		assert_eq!(json["persons"][3]["assignments"], json!([{ "activityId": 2, "assignmentCode": "staff-judge", "stationNumber": null }]));
+	}
+
+	#[test]
+	fn diff_describes_new_groups_and_moved_competitors() {
+		let before = from_value(fixture()).unwrap();
+		let mut grouped = copy(&before);
+		set_groups(&mut grouped, "333", 1, &[vec![1, 2], vec![3]]);
+		assert_eq!(
+			diff(&before, &grouped).to_string(),
+			"Activities: + 333-r1-g1 (08:00-08:30 UTC), + 333-r1-g2 (08:30-09:00 UTC); \
+			Alice Delegate: + Competitor in 333-r1-g1 at station 1; \
+			Bob Speedcuber: + Competitor in 333-r1-g1 at station 2; \
+			Carol Newcomer: + Competitor in 333-r1-g2 at station 1"
+		);
+
+		let mut moved = copy(&grouped);
+		set_groups(&mut moved, "333", 1, &[vec![1], vec![2, 3]]);
+		let diff = diff(&grouped, &moved);
+		assert!(diff.activities.is_empty());
+		assert_eq!(
+			diff.to_string(),
+			"Bob Speedcuber: ~ Competitor from 333-r1-g1 at station 2 to 333-r1-g2 at station 1; \
+			Carol Newcomer: ~ Competitor from 333-r1-g2 at station 1 to 333-r1-g2 at station 2"
+		);
+	}
+
+	#[test]
+	fn activities_are_matched_by_code_and_not_by_id() {
+		let mut before = from_value(fixture()).unwrap();
+		set_groups(&mut before, "333", 1, &[vec![1, 2, 3]]);
+		let mut json = to_json(&before);
+		json["schedule"]["venues"][0]["rooms"][0]["activities"][0]["childActivities"][0]["id"] = json!(7);
+		for person in json["persons"].as_array_mut().unwrap() {
+			for assignment in person["assignments"].as_array_mut().unwrap() {
+				assignment["activityId"] = json!(7);
+			}
+		}
+		let after = from_value(json).unwrap();
+
+		assert!(diff(&before, &after).is_empty());
+	}
+
+	#[test]
+	fn restoring_puts_back_the_groups_and_keeps_everything_else() {
+		let mut snapshot = from_value(fixture()).unwrap();
+		set_groups(&mut snapshot, "333", 1, &[vec![1, 2], vec![3]]);
+		let mut json = to_json(&snapshot);
+		json["persons"][3]["assignments"] = json!([{ "activityId": 3, "assignmentCode": "staff-judge", "stationNumber": null }]);
+		let snapshot = from_value(json).unwrap();
+		let mut current = copy(&snapshot);
+		set_groups(&mut current, "333", 1, &[vec![3, 2, 1]]);
+		let mut json = to_json(&current);
+		json["persons"][3]["name"] = json!("David Spectator");
+		let mut current = from_value(json).unwrap();
+
+		restore_groups(&mut current, &snapshot);
+
+		let (restored, snapshot) = (to_json(&current), to_json(&snapshot));
+		let children = |json: &Value| json["schedule"]["venues"][0]["rooms"][0]["activities"][0]["childActivities"].clone();
+		assert_eq!(children(&restored), children(&snapshot));
+		let assignments = |json: &Value| persons(json).map(|person| person["assignments"].clone()).collect::<Vec<_>>();
+		assert_eq!(assignments(&restored), assignments(&snapshot));
+		assert_eq!(restored["persons"][3]["name"], "David Spectator");
+	}
+
+	#[test]
+	fn round_diff_only_looks_at_the_given_rounds() {
+		let mut json = fixture();
+		json["schedule"]["venues"][0]["rooms"][0]["activities"].as_array_mut().unwrap().push(json!({
+			"id": 10,
+			"name": "2x2x2 Cube, Round 1",
+			"activityCode": "222-r1",
+			"startTime": "2099-06-05T09:00:00Z",
+			"endTime": "2099-06-05T10:00:00Z",
+			"childActivities": [],
+			"extensions": []
+		}));
+		let mut before = from_value(json).unwrap();
+		set_groups(&mut before, "333", 1, &[vec![1, 2], vec![3]]);
+		set_groups(&mut before, "222", 1, &[vec![1, 2]]);
+		let mut after = copy(&before);
+		set_groups(&mut after, "333", 1, &[vec![2, 1], vec![3]]);
+		set_groups(&mut after, "222", 1, &[vec![1], vec![2]]);
+
+		assert_eq!(
+			round_diff(&before, &after, &["333-r1".to_string()]).to_string(),
+			"Alice Delegate: ~ Competitor from 333-r1-g1 at station 1 to 333-r1-g1 at station 2; \
+			Bob Speedcuber: ~ Competitor from 333-r1-g1 at station 2 to 333-r1-g1 at station 1"
+		);
+		assert!(round_diff(&before, &after, &["444-r1".to_string()]).is_empty());
+		assert!(!round_diff(&before, &after, &["222-r1".to_string()]).activities.is_empty());
+	}
+}
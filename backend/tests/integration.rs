@@ -0,0 +1,481 @@
+//! Drives the server through offline sessions, the round page and the exports. Nothing here talks to the WCA website.
+
+use std::fs::read_to_string;
+
+use actix_http::Request;
+use actix_web::{
+	body::MessageBody,
+	cookie::Cookie,
+	dev::{Service, ServiceResponse},
+	http::StatusCode,
+	test::{self, TestRequest},
+	App,
+};
+use backend::Config;
+use common::{from_base_64, to_base_64, Competitors, PdfRequest};
+use serde_json::{json, Value};
+
+const COMPETITION: &str = "MockOpen2099";
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+async fn app() -> impl Service<Request, Response = ServiceResponse<impl MessageBody>, Error = actix_web::Error> {
+	app_with("").await
+}
+
+/// The app with extra top level settings in its config.
+async fn app_with(settings: &str) -> impl Service<Request, Response = ServiceResponse<impl MessageBody>, Error = actix_web::Error> {
+	let config = Config::parse(&format!(
+		r#"
+		{settings}
+		client_id = "client"
+		client_secret = "secret"
+		redirect_uri = "http://localhost:8080/validated"
+		auth_url = "https://www.worldcubeassociation.org/oauth/authorize"
+		pkg_path = "."
+		wcif_dir = "{fixtures}"
+
+		[monitoring]
+		token = "monitoring-token"
+		"#,
+		fixtures = FIXTURES,
+	))
+	.unwrap();
+	test::init_service(App::new().configure(backend::configure(backend::init(config).unwrap()))).await
+}
+
+fn cookie(response: &ServiceResponse<impl MessageBody>, name: &str) -> Cookie<'static> {
+	response
+		.response()
+		.cookies()
+		.find(|c| c.name() == name)
+		.unwrap_or_else(|| panic!("Response has no {name} cookie"))
+		.into_owned()
+}
+
+async fn body(response: ServiceResponse<impl MessageBody>) -> String {
+	String::from_utf8(test::read_body(response).await.to_vec()).unwrap()
+}
+
+/// The csrf token of the session, taken from the meta tag of a page.
+fn csrf_token(page: &str) -> String {
+	let start = page.find("name = \"csrf-token\" content = \"").expect("Page has no csrf token") + 31;
+	page[start..].split('"').next().unwrap().to_owned()
+}
+
+fn pdf_request(competition: &str, wcif: bool) -> PdfRequest {
+	PdfRequest {
+		competition: competition.to_owned(),
+		stages: 1,
+		stations: 10,
+		groups: vec![vec![1, 2, 3]],
+		wcif,
+		event: "333".to_owned(),
+		round: 1,
+		seperate_stages: false,
+		staff: false,
+	}
+}
+
+fn fixture() -> Value {
+	serde_json::from_str(&read_to_string(format!("{FIXTURES}/{COMPETITION}.json")).unwrap()).unwrap()
+}
+
+/// Starts an offline session the way the offline page does and returns the session cookie. The wcif is uploaded if
+/// given and opened from the wcif directory otherwise.
+async fn start_offline(
+	app: &impl Service<Request, Response = ServiceResponse<impl MessageBody>, Error = actix_web::Error>,
+	wcif: Option<&Value>,
+) -> Cookie<'static> {
+	let response = test::call_service(app, TestRequest::get().uri("/offline").to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let state = cookie(&response, "scorecards_state");
+	assert!(body(response).await.contains(COMPETITION));
+
+	let request = match wcif {
+		Some(wcif) => TestRequest::post()
+			.uri(&format!("/offline?state={}", state.value()))
+			.set_payload(serde_json::to_vec(wcif).unwrap()),
+		None => TestRequest::post()
+			.uri(&format!("/offline/{COMPETITION}"))
+			.set_form([("state", state.value())]),
+	};
+	let response = test::call_service(app, request.cookie(state).to_request()).await;
+	assert_eq!(response.status(), StatusCode::SEE_OTHER);
+	assert_eq!(response.headers().get("Location").unwrap(), &format!("/{COMPETITION}"));
+	cookie(&response, "scorecards")
+}
+
+#[actix_web::test]
+async fn patching_shows_a_preview_first() {
+	let app = app().await;
+	let session = start_offline(&app, Some(&fixture())).await;
+
+	let response = test::call_service(&app, TestRequest::get().uri(&format!("/{COMPETITION}")).cookie(session.clone()).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(body(response).await.contains("333"));
+
+	let response = test::call_service(
+		&app,
+		TestRequest::get()
+			.uri(&format!("/{COMPETITION}/333/1?stages=1&stations=10&seperate_stages=false"))
+			.cookie(session.clone())
+			.to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let csrf = csrf_token(&body(response).await);
+
+	let data = to_base_64(pdf_request(COMPETITION, true));
+	let response = test::call_service(&app, TestRequest::get().uri(&format!("/pdf?data={data}")).cookie(session.clone()).to_request()).await;
+	assert_eq!(response.status(), StatusCode::SEE_OTHER);
+	let preview = response.headers().get("Location").unwrap().to_str().unwrap().to_owned();
+	let response = test::call_service(&app, TestRequest::get().uri(&preview).cookie(session).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let page = body(response).await;
+	assert!(page.contains("+ 333-r1-g1"));
+	assert!(page.contains("Competitor in 333-r1-g1 at station 1"));
+	// The patch is a form, so the csrf token is never part of a url.
+	assert!(page.contains(&format!("<input type = \"hidden\" name = \"csrf\" value = \"{csrf}\">")));
+	assert!(!page.contains("csrf="));
+}
+
+#[actix_web::test]
+async fn login_redirects_to_the_wca_website() {
+	let app = app().await;
+	let response = test::call_service(&app, TestRequest::get().uri("/").to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let state = cookie(&response, "scorecards_state");
+	assert!(body(response).await.contains(&format!("state={}", state.value())));
+}
+
+#[actix_web::test]
+async fn login_with_wrong_state_is_rejected() {
+	let app = app().await;
+	let response = test::call_service(&app, TestRequest::get().uri("/").to_request()).await;
+	let state = cookie(&response, "scorecards_state");
+	let response = test::call_service(
+		&app,
+		TestRequest::get()
+			.uri("/validated?code=code&state=forged")
+			.cookie(state)
+			.to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn pdf_without_csrf_token_is_rejected() {
+	let app = app().await;
+	let session = start_offline(&app, None).await;
+	let response = test::call_service(
+		&app,
+		TestRequest::post()
+			.uri("/pdf")
+			.set_form([("data", to_base_64(pdf_request(COMPETITION, true))), ("csrf", "forged".to_owned())])
+			.cookie(session)
+			.to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn offline_scorecards_need_a_wca_login() {
+	let app = app().await;
+	let session = start_offline(&app, None).await;
+	let response = test::call_service(
+		&app,
+		TestRequest::get().uri(&format!("/pdf?data={}", to_base_64(pdf_request(COMPETITION, false)))).cookie(session.clone()).to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+	assert!(body(response).await.contains("logged in to the WCA website"));
+
+	// The wcif of the session is kept.
+	let response = test::call_service(&app, TestRequest::get().uri(&format!("/{COMPETITION}/wcif")).cookie(session).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn unknown_competition_is_not_found() {
+	let app = app().await;
+	let session = start_offline(&app, None).await;
+	let response = test::call_service(&app, TestRequest::get().uri("/NoSuchOpen2099").cookie(session).to_request()).await;
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn unknown_round_is_not_found() {
+	let app = app().await;
+	let session = start_offline(&app, None).await;
+	let response = test::call_service(
+		&app,
+		TestRequest::get()
+			.uri(&format!("/{COMPETITION}/333/4?stages=1&stations=10&seperate_stages=false"))
+			.cookie(session)
+			.to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn batch_needs_a_stage_and_a_station() {
+	let app = app().await;
+	let session = start_offline(&app, None).await;
+
+	for (stages, stations) in [(0, 10), (1, 0)] {
+		let uri = format!("/{COMPETITION}/batch?stages={stages}&stations={stations}&seperate_stages=false&rounds=333-r1&format=pdf");
+		let response = test::call_service(&app, TestRequest::get().uri(&uri).cookie(session.clone()).to_request()).await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+	}
+}
+
+#[actix_web::test]
+async fn pages_need_a_session() {
+	let app = app().await;
+	let response = test::call_service(&app, TestRequest::get().uri(&format!("/{COMPETITION}")).to_request()).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn scripts_get_a_401_instead_of_the_login_redirect() {
+	let app = app().await;
+	let expired = Cookie::new("scorecards", "unknown-session");
+
+	let response = test::call_service(
+		&app,
+		TestRequest::get()
+			.uri(&format!("/{COMPETITION}"))
+			.insert_header(("Accept", "text/html,application/xhtml+xml"))
+			.cookie(expired.clone())
+			.to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::SEE_OTHER);
+	assert_eq!(response.headers().get("Location").unwrap(), "/");
+
+	let response = test::call_service(&app, TestRequest::post().uri("/keepalive").cookie(expired).to_request()).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+	assert!(cookie(&response, "scorecards").value().is_empty());
+	assert_eq!(body(response).await, "Your session has expired. Please log in again.");
+}
+
+#[actix_web::test]
+async fn metrics_need_the_monitoring_token() {
+	let app = app().await;
+	// Not even local requests get in without the token, as they may come through a reverse proxy.
+	let response = test::call_service(&app, TestRequest::get().uri("/metrics").peer_addr("127.0.0.1:9000".parse().unwrap()).to_request()).await;
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+	let response = test::call_service(
+		&app,
+		TestRequest::get().uri("/metrics").insert_header(("Authorization", "Bearer wrong-token")).to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+	let response = test::call_service(
+		&app,
+		TestRequest::get().uri("/metrics").insert_header(("Authorization", "Bearer monitoring-token")).to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(body(response).await.contains("\nscorecards_sessions 0\n"));
+}
+
+#[actix_web::test]
+async fn timed_out_sessions_are_not_counted() {
+	for (settings, sessions) in [("", 1), ("session_timeout = 0", 0)] {
+		let app = app_with(settings).await;
+		start_offline(&app, None).await;
+		let response = test::call_service(
+			&app,
+			TestRequest::get().uri("/metrics").insert_header(("Authorization", "Bearer monitoring-token")).to_request(),
+		)
+		.await;
+		assert!(body(response).await.contains(&format!("\nscorecards_sessions {sessions}\n")));
+	}
+}
+
+#[actix_web::test]
+async fn names_on_the_constraints_page_are_escaped() {
+	let app = app().await;
+	let mut wcif = fixture();
+	wcif["persons"][1]["name"] = json!("<script>alert(1)</script>");
+	let session = start_offline(&app, Some(&wcif)).await;
+	let response = test::call_service(&app, TestRequest::get().uri(&format!("/{COMPETITION}/constraints")).cookie(session).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let page = body(response).await;
+	assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+	assert!(!page.contains("<script>alert(1)"));
+}
+
+#[actix_web::test]
+async fn drafts_arriving_out_of_order_are_ignored() {
+	let app = app().await;
+	let session = start_offline(&app, None).await;
+	let round = format!("/{COMPETITION}/333/1?stages=1&stations=10&seperate_stages=false");
+	let editor = |page: String| -> Competitors {
+		let start = page.find("start(\"").expect("Page starts the editor") + 7;
+		from_base_64(page[start..].split('"').next().unwrap())
+	};
+	let response = test::call_service(&app, TestRequest::get().uri(&round).cookie(session.clone()).to_request()).await;
+	let page = body(response).await;
+	let csrf = csrf_token(&page);
+	assert_eq!(editor(page).draft_revision, 0);
+
+	let draft = format!("/{COMPETITION}/333/1/draft");
+	for (revision, groups) in [(2, vec![vec![3u64, 2, 1]]), (1, vec![vec![1, 2, 3]])] {
+		let response = test::call_service(
+			&app,
+			TestRequest::post()
+				.uri(&format!("{draft}?revision={revision}"))
+				.insert_header(("X-CSRF-Token", csrf.as_str()))
+				.set_payload(to_base_64(groups))
+				.cookie(session.clone())
+				.to_request(),
+		)
+		.await;
+		assert_eq!(response.status(), StatusCode::NO_CONTENT);
+	}
+	let response = test::call_service(
+		&app,
+		TestRequest::delete().uri(&format!("{draft}?revision=1")).insert_header(("X-CSRF-Token", csrf.as_str())).cookie(session.clone()).to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+	let response = test::call_service(&app, TestRequest::get().uri(&round).cookie(session).to_request()).await;
+	let editor = editor(body(response).await);
+	assert_eq!(editor.draft, Some(vec![vec![3, 2, 1]]));
+	assert_eq!(editor.draft_revision, 2);
+}
+
+#[actix_web::test]
+async fn offline_wcif_can_be_downloaded() {
+	let app = app().await;
+	let session = start_offline(&app, Some(&fixture())).await;
+	let response = test::call_service(&app, TestRequest::get().uri(&format!("/{COMPETITION}/wcif")).cookie(session).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let wcif: Value = serde_json::from_str(&body(response).await).unwrap();
+	assert_eq!(wcif["id"], COMPETITION);
+	assert_eq!(wcif["persons"].as_array().unwrap().len(), 4);
+}
+
+#[actix_web::test]
+async fn offline_wcif_can_be_opened_from_the_wcif_directory() {
+	let app = app().await;
+	let session = start_offline(&app, None).await;
+	let response = test::call_service(&app, TestRequest::get().uri("/validated").cookie(session).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(body(response).await.contains("Mock Open 2099"));
+}
+
+#[actix_web::test]
+async fn offline_upload_needs_the_state_cookie() {
+	let app = app().await;
+	let response = test::call_service(
+		&app,
+		TestRequest::post()
+			.uri("/offline?state=forged")
+			.set_payload(serde_json::to_vec(&fixture()).unwrap())
+			.to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+/// The fixture with Alice, Bob and Carol in the only group of 333-r1, at stations 2, 1 and 3.
+fn assigned_fixture() -> Value {
+	let mut wcif = fixture();
+	wcif["schedule"]["venues"][0]["rooms"][0]["activities"][0]["childActivities"] = json!([{
+		"id": 2,
+		"name": "3x3x3 Cube, Round 1, Group 1",
+		"activityCode": "333-r1-g1",
+		"startTime": "2099-06-05T08:00:00Z",
+		"endTime": "2099-06-05T09:00:00Z",
+		"childActivities": [],
+		"extensions": []
+	}]);
+	for (person, station) in [(0, 2), (1, 1), (2, 3)] {
+		wcif["persons"][person]["assignments"] = json!([{ "activityId": 2, "assignmentCode": "competitor", "stationNumber": station }]);
+	}
+	wcif
+}
+
+#[actix_web::test]
+async fn assigned_groups_can_be_exported() {
+	let app = app().await;
+	let session = start_offline(&app, Some(&assigned_fixture())).await;
+
+	let response = test::call_service(
+		&app,
+		TestRequest::get().uri(&format!("/{COMPETITION}/export?format=csv")).cookie(session.clone()).to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let csv = body(response).await;
+	let lines: Vec<&str> = csv.trim_start_matches('\u{feff}').lines().collect();
+	assert_eq!(lines, [
+		"Round,Group,Station,Registrant ID,Name,WCA ID",
+		"333-r1,1,1,2,Bob Speedcuber,2015SPEE01",
+		"333-r1,1,2,1,Alice Delegate,2010DELE01",
+		"333-r1,1,3,3,Carol Newcomer,",
+	]);
+
+	let response = test::call_service(
+		&app,
+		TestRequest::get().uri(&format!("/{COMPETITION}/export?format=xlsx")).cookie(session).to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(response.headers().get("Content-Disposition").unwrap().to_str().unwrap().contains(".xlsx"));
+	assert!(test::read_body(response).await.starts_with(b"PK"));
+}
+
+#[actix_web::test]
+async fn names_are_not_run_as_formulas_by_spreadsheets() {
+	let app = app().await;
+	let mut wcif = assigned_fixture();
+	wcif["persons"][0]["name"] = json!("=HYPERLINK(\"https://example.com\",\"Alice\")");
+	wcif["persons"][1]["name"] = json!("@SUM(1+1)");
+	wcif["persons"][2]["name"] = json!("-2+3");
+	let session = start_offline(&app, Some(&wcif)).await;
+
+	let response = test::call_service(
+		&app,
+		TestRequest::get().uri(&format!("/{COMPETITION}/export?format=csv")).cookie(session).to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let csv = body(response).await;
+	let lines: Vec<&str> = csv.trim_start_matches('\u{feff}').lines().skip(1).collect();
+	assert_eq!(lines, [
+		"333-r1,1,1,2,'@SUM(1+1),2015SPEE01",
+		"333-r1,1,2,1,\"'=HYPERLINK(\"\"https://example.com\"\",\"\"Alice\"\")\",2010DELE01",
+		"333-r1,1,3,3,'-2+3,",
+	]);
+}
+
+#[actix_web::test]
+async fn unsaved_groups_of_the_editor_can_be_exported() {
+	let app = app().await;
+	let session = start_offline(&app, None).await;
+	let mut request = pdf_request(COMPETITION, false);
+	request.groups = vec![vec![2], vec![3, 1]];
+	let response = test::call_service(
+		&app,
+		TestRequest::get().uri(&format!("/export?data={}&format=csv", to_base_64(request))).cookie(session).to_request(),
+	)
+	.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.headers().get("Content-Disposition").unwrap(), "attachment; filename=\"MockOpen2099-333-r1-groups.csv\"");
+	let csv = body(response).await;
+	let lines: Vec<&str> = csv.lines().skip(1).collect();
+	assert_eq!(lines, [
+		"333-r1,1,1,2,Bob Speedcuber,2015SPEE01",
+		"333-r1,2,1,3,Carol Newcomer,",
+		"333-r1,2,2,1,Alice Delegate,2010DELE01",
+	]);
+}
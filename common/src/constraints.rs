@@ -0,0 +1,144 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Serialize, Deserialize};
+
+/// Pairs of registrant ids which should be in the same group or in different groups.
+/// Stored in the `dve.GroupingConstraints` wcif extension.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Constraints {
+    #[serde(default)]
+    pub keep_together: Vec<(u64, u64)>,
+    #[serde(default)]
+    pub keep_apart: Vec<(u64, u64)>,
+}
+
+impl Constraints {
+    fn constrained(&self) -> HashSet<u64> {
+        self.keep_together.iter()
+            .chain(&self.keep_apart)
+            .flat_map(|&(a, b)| [a, b])
+            .collect()
+    }
+
+    /// Pairs which are not satisfied by the groups. Pairs where one of the persons is not in the round are ignored.
+    pub fn violations(&self, groups: &[Vec<u64>]) -> Vec<(u64, u64)> {
+        let group_of = group_of(groups);
+        let together = self.keep_together.iter()
+            .filter(|(a, b)| matches!((group_of.get(a), group_of.get(b)), (Some(x), Some(y)) if x != y));
+        let apart = self.keep_apart.iter()
+            .filter(|(a, b)| matches!((group_of.get(a), group_of.get(b)), (Some(x), Some(y)) if x == y));
+        together.chain(apart).copied().collect()
+    }
+
+    /// Swaps competitors between groups until as many constraints as possible are satisfied. Only competitors without
+    /// constraints are swapped with, and a swap is only made if `allowed` accepts both competitors in their new groups.
+    pub fn apply(&self, groups: &mut [Vec<u64>], allowed: impl Fn(u64, usize) -> bool) {
+        let constrained = self.constrained();
+        for (a, b) in self.keep_together.clone() {
+            let group_of = group_of(groups);
+            let (Some(&group_a), Some(&group_b)) = (group_of.get(&a), group_of.get(&b)) else {
+                continue;
+            };
+            if group_a != group_b && !swap_into(groups, b, group_a, &constrained, &allowed) {
+                swap_into(groups, a, group_b, &constrained, &allowed);
+            }
+        }
+        for (a, b) in self.keep_apart.clone() {
+            let group_of = group_of(groups);
+            let (Some(&group_a), Some(&group_b)) = (group_of.get(&a), group_of.get(&b)) else {
+                continue;
+            };
+            if group_a != group_b {
+                continue;
+            }
+            for target in (0..groups.len()).filter(|&target| target != group_a) {
+                if swap_into(groups, b, target, &constrained, &allowed) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Swaps a competitor into the target group with someone without constraints. Returns whether a swap was made.
+fn swap_into(groups: &mut [Vec<u64>], id: u64, target: usize, constrained: &HashSet<u64>, allowed: &impl Fn(u64, usize) -> bool) -> bool {
+    let Some((group, number)) = groups.iter()
+        .enumerate()
+        .find_map(|(group, members)| Some((group, members.iter().position(|&other| other == id)?))) else {
+        return false;
+    };
+    if !allowed(id, target) {
+        return false;
+    }
+    let partner = groups[target].iter()
+        .position(|other| !constrained.contains(other) && allowed(*other, group));
+    match partner {
+        Some(position) => {
+            groups[group][number] = groups[target][position];
+            groups[target][position] = id;
+            true
+        },
+        None => false,
+    }
+}
+
+fn group_of(groups: &[Vec<u64>]) -> HashMap<u64, usize> {
+    groups.iter()
+        .enumerate()
+        .flat_map(|(group, members)| members.iter().map(move |id| (*id, group)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups() -> Vec<Vec<u64>> {
+        vec![vec![1, 2, 3], vec![4, 5, 6]]
+    }
+
+    #[test]
+    fn kept_together_by_swapping_with_someone_unconstrained() {
+        let constraints = Constraints { keep_together: vec![(1, 4)], ..Constraints::default() };
+        let mut groups = groups();
+        constraints.apply(&mut groups, |_, _| true);
+        assert_eq!(groups, [vec![1, 4, 3], vec![2, 5, 6]]);
+        assert!(constraints.violations(&groups).is_empty());
+    }
+
+    #[test]
+    fn kept_apart_by_swapping_with_someone_unconstrained() {
+        let constraints = Constraints { keep_apart: vec![(1, 2)], ..Constraints::default() };
+        let mut groups = groups();
+        constraints.apply(&mut groups, |_, _| true);
+        assert_eq!(groups, [vec![1, 4, 3], vec![2, 5, 6]]);
+        assert!(constraints.violations(&groups).is_empty());
+    }
+
+    #[test]
+    fn unsatisfiable_constraints_are_reported() {
+        let constraints = Constraints { keep_together: vec![(1, 4)], keep_apart: vec![(1, 4)] };
+        let mut groups = groups();
+        constraints.apply(&mut groups, |_, _| true);
+        assert_eq!(constraints.violations(&groups), [(1, 4)]);
+    }
+
+    #[test]
+    fn swaps_which_are_not_allowed_are_not_made() {
+        let constraints = Constraints { keep_together: vec![(1, 4)], ..Constraints::default() };
+        let mut groups = groups();
+        constraints.apply(&mut groups, |id, group| (id, group) != (4, 0) && (id, group) != (1, 1));
+        assert_eq!(groups, self::groups());
+        assert_eq!(constraints.violations(&groups), [(1, 4)]);
+    }
+
+    #[test]
+    fn persons_outside_the_round_are_ignored() {
+        let constraints = Constraints { keep_together: vec![(1, 7)], keep_apart: vec![(8, 9)] };
+        let mut groups = groups();
+        constraints.apply(&mut groups, |_, _| true);
+        assert_eq!(groups, self::groups());
+        assert!(constraints.violations(&groups).is_empty());
+    }
+}
@@ -1,19 +1,39 @@
-use std::collections::HashMap;
+mod constraints;
+mod schedule;
+
+use std::collections::{HashMap, HashSet};
 
 use base64::{engine::{GeneralPurpose, GeneralPurposeConfig}, alphabet::URL_SAFE, Engine};
 use serde::{Serialize, Deserialize, de::DeserializeOwned};
 
+pub use constraints::Constraints;
+pub use schedule::Schedule;
+
 #[derive(Serialize, Deserialize)]
 pub struct Competitors {
     pub competition: String,
     pub competitors: Vec<u64>,
     pub names: HashMap<u64, String>,
     pub delegates: Vec<u64>,
+    /// Competitors without a WCA ID.
+    pub newcomers: Vec<u64>,
     pub stages: u64,
     pub stations: u64,
     pub event: String,
     pub round: u64,
     pub seperate_stages: bool,
+    /// Seed of each competitor, 0 being the fastest. Competitors without a result are absent.
+    pub rankings: HashMap<u64, u64>,
+    pub seeded: bool,
+    /// Scheduled time of the round as unix timestamps, if it is in the schedule.
+    pub round_window: Option<(i64, i64)>,
+    /// Time windows each competitor is already assigned to in other rounds.
+    pub busy: HashMap<u64, Vec<(i64, i64)>>,
+    /// Groups saved by the user the last time the round was edited.
+    pub draft: Option<Vec<Vec<u64>>>,
+    /// Revision of the last change to the draft. The editor numbers its changes from here on.
+    pub draft_revision: u64,
+    pub constraints: Constraints,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -63,6 +83,60 @@ pub struct PdfRequest {
     pub event: String,
     pub round: u64,
     pub seperate_stages: bool,
+    /// Generate judge, scrambler and runner assignments. Only used when patching the wcif.
+    pub staff: bool,
+}
+
+/// Splits the competitors into groups with delegates and newcomers spread evenly. When rankings are given the fastest
+/// competitors are put in the last group. Stages and stations have to be at least 1.
+pub fn make_groups(competitors: Vec<u64>, delegates: Vec<u64>, newcomers: Vec<u64>, stages: u64, stations: u64, rankings: Option<&HashMap<u64, u64>>) -> Vec<Vec<u64>> {
+    let capacity = stages * stations;
+    let no_of_groups = (competitors.len() as u64).div_ceil(capacity);
+    let map: HashSet<_> = delegates.into_iter().collect();
+    let newcomer_map: HashSet<_> = newcomers.into_iter().collect();
+    let mut competing_delegates: Vec<_> = competitors.iter().filter(|id| map.contains(id)).cloned().collect();  
+    let mut competing_newcomers: Vec<_> = competitors.iter().filter(|id| !map.contains(id) && newcomer_map.contains(id)).cloned().collect();
+    let mut competing_others: Vec<_> = competitors.iter().filter(|id| !map.contains(id) && !newcomer_map.contains(id)).cloned().collect(); 
+    if let Some(rankings) = rankings {
+        // Groups are taken from the back, so the slowest competitors have to be last.
+        let seed = |id: &u64| rankings.get(id).copied().unwrap_or(u64::MAX);
+        competing_delegates.sort_by_key(seed);
+        competing_newcomers.sort_by_key(seed);
+        competing_others.sort_by_key(seed);
+    }
+    let delegate_distribution = distribution(competing_delegates.len() as u64, no_of_groups);
+    let newcomer_distribution = distribution(competing_newcomers.len() as u64, no_of_groups);
+    let competitor_distribution = distribution(competitors.len() as u64, no_of_groups);
+    (0..no_of_groups as usize).map(|idx| {
+            let size = competitor_distribution[idx] as usize;
+            let mut group = take_last(&mut competing_delegates, delegate_distribution[idx] as usize);
+            group.extend(take_last(&mut competing_newcomers, (newcomer_distribution[idx] as usize).min(size.saturating_sub(group.len()))));
+            // If the delegates and newcomers do not add up exactly the group is filled with whoever is left.
+            group.extend(take_last(&mut competing_others, size.saturating_sub(group.len())));
+            group.extend(take_last(&mut competing_newcomers, size.saturating_sub(group.len())));
+            group.extend(take_last(&mut competing_delegates, size.saturating_sub(group.len())));
+            group
+        }).collect()
+}
+
+/// Moves competitors out of groups which clash with their other assignments, then satisfies as many constraints as
+/// possible without adding clashes. This is what the group editor and the batch download do after `make_groups`.
+pub fn apply_schedule_and_constraints(groups: &mut [Vec<u64>], schedule: &Schedule, constraints: &Constraints) {
+    schedule.resolve_conflicts(groups);
+    let no_of_groups = groups.len();
+    constraints.apply(groups, |id, group| !schedule.conflicts(id, group, no_of_groups));
+}
+
+fn take_last(competitors: &mut Vec<u64>, count: usize) -> Vec<u64> {
+    competitors.split_off(competitors.len().saturating_sub(count))
+}
+
+fn distribution(mut remaining: u64, no_of_groups: u64) -> Vec<u64> {
+    (0..no_of_groups).map(|group| {
+            let per_group = remaining / (no_of_groups - group);
+            remaining -= per_group;
+            per_group
+        }).collect()
 }
 
 pub fn to_base_64<T>(data: T) -> String where T: Serialize {
@@ -72,7 +146,66 @@ pub fn to_base_64<T>(data: T) -> String where T: Serialize {
 }
 
 pub fn from_base_64<T>(base64: &str) -> T where T: DeserializeOwned { 
+    decode_base_64(base64).unwrap()
+}
+
+/// Like `from_base_64`, but returns an error message for malformed input instead of panicking.
+pub fn decode_base_64<T>(base64: &str) -> Result<T, String> where T: DeserializeOwned {
     let engine = GeneralPurpose::new(&URL_SAFE, GeneralPurposeConfig::new());
-    let bytes = engine.decode(base64).unwrap();
-    postcard::from_bytes(&bytes).unwrap()
+    let bytes = engine.decode(base64).map_err(|e| e.to_string())?;
+    postcard::from_bytes(&bytes).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(groups: Vec<Vec<u64>>) -> Vec<Vec<u64>> {
+        groups.into_iter()
+            .map(|mut group| {
+                group.sort();
+                group
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seeded_groups_put_the_fastest_last() {
+        let rankings: HashMap<u64, u64> = (1..=5).map(|id| (id, id - 1)).collect();
+        let groups = make_groups((1..=6).collect(), vec![], vec![], 1, 3, Some(&rankings));
+        // Competitor 6 has no result and counts as the slowest.
+        assert_eq!(sorted(groups), [vec![4, 5, 6], vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn newcomers_are_spread_evenly() {
+        let groups = make_groups((1..=9).collect(), vec![], vec![1, 2, 3, 4], 1, 3, None);
+        let newcomers: Vec<usize> = groups.iter().map(|group| group.iter().filter(|id| **id <= 4).count()).collect();
+        assert_eq!(newcomers, [1, 1, 2]);
+    }
+
+    #[test]
+    fn newcomers_fill_up_groups_without_others() {
+        let groups = make_groups((1..=4).collect(), vec![1], vec![2, 3, 4], 1, 2, None);
+        assert_eq!(sorted(groups), [vec![3, 4], vec![1, 2]]);
+    }
+
+    #[test]
+    fn nobody_makes_no_groups() {
+        assert!(make_groups(vec![], vec![], vec![], 1, 3, None).is_empty());
+    }
+
+    #[test]
+    fn distribution_adds_up() {
+        assert_eq!(distribution(7, 3), [2, 2, 3]);
+        assert_eq!(distribution(1, 3), [0, 0, 1]);
+    }
+
+    #[test]
+    fn seeding_keeps_delegates_spread() {
+        let rankings: HashMap<u64, u64> = (1..=6).map(|id| (id, id - 1)).collect();
+        let groups = make_groups((1..=6).collect(), vec![1, 2], vec![], 1, 3, Some(&rankings));
+        assert!(groups.iter().all(|group| group.len() == 3));
+        assert!(groups.iter().all(|group| group.iter().filter(|id| **id <= 2).count() == 1));
+    }
 }
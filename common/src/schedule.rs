@@ -0,0 +1,102 @@
+use std::collections::{HashMap, HashSet};
+
+/// Scheduling information used to keep competitors out of groups which clash with their other assignments.
+#[derive(Clone)]
+pub struct Schedule {
+    round_window: Option<(i64, i64)>,
+    busy: HashMap<u64, Vec<(i64, i64)>>,
+}
+
+impl Schedule {
+    pub fn new(round_window: Option<(i64, i64)>, busy: HashMap<u64, Vec<(i64, i64)>>) -> Schedule {
+        Schedule { round_window, busy }
+    }
+
+    /// Time window of a group, assuming the groups split the round evenly.
+    fn group_window(&self, group: usize, no_of_groups: usize) -> Option<(i64, i64)> {
+        let (start, end) = self.round_window?;
+        let length = end - start;
+        let no_of_groups = no_of_groups as i64;
+        let group = group as i64;
+        Some((start + length * group / no_of_groups, start + length * (group + 1) / no_of_groups))
+    }
+
+    pub fn conflicts(&self, id: u64, group: usize, no_of_groups: usize) -> bool {
+        let Some((start, end)) = self.group_window(group, no_of_groups) else {
+            return false;
+        };
+        self.busy.get(&id)
+            .is_some_and(|windows| windows.iter().any(|&(s, e)| s < end && start < e))
+    }
+
+    /// Competitors which are in a group overlapping one of their other assignments.
+    pub fn conflicting(&self, groups: &[Vec<u64>]) -> HashSet<u64> {
+        groups.iter()
+            .enumerate()
+            .flat_map(|(group, members)| members.iter()
+                .filter(move |id| self.conflicts(**id, group, groups.len()))
+                .copied())
+            .collect()
+    }
+
+    /// Swaps competitors between groups to get rid of as many conflicts as possible. Competitors are
+    /// preferably swapped into the nearest group so seeding is disturbed as little as possible.
+    pub fn resolve_conflicts(&self, groups: &mut [Vec<u64>]) {
+        let no_of_groups = groups.len();
+        for group in 0..no_of_groups {
+            for idx in 0..groups[group].len() {
+                let id = groups[group][idx];
+                if !self.conflicts(id, group, no_of_groups) {
+                    continue;
+                }
+                let mut targets: Vec<_> = (0..no_of_groups)
+                    .filter(|&target| target != group && !self.conflicts(id, target, no_of_groups))
+                    .collect();
+                targets.sort_by_key(|target| target.abs_diff(group));
+                for target in targets {
+                    let swap = groups[target].iter()
+                        .position(|&other| !self.conflicts(other, group, no_of_groups));
+                    if let Some(pos) = swap {
+                        groups[group][idx] = groups[target][pos];
+                        groups[target][pos] = id;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A round from 0 to 300 split into three groups, with competitor 1 busy during the first group.
+    fn schedule() -> Schedule {
+        Schedule::new(Some((0, 300)), HashMap::from([(1, vec![(0, 50)])]))
+    }
+
+    #[test]
+    fn overlapping_groups_conflict() {
+        let schedule = schedule();
+        assert!(schedule.conflicts(1, 0, 3));
+        assert!(!schedule.conflicts(1, 1, 3));
+        assert!(!schedule.conflicts(2, 0, 3));
+        assert_eq!(schedule.conflicting(&[vec![1, 2], vec![3, 4], vec![5, 6]]), HashSet::from([1]));
+    }
+
+    #[test]
+    fn conflicts_are_resolved_by_swapping_into_the_nearest_group() {
+        let mut groups = vec![vec![1, 2], vec![3, 4], vec![5, 6]];
+        schedule().resolve_conflicts(&mut groups);
+        assert_eq!(groups, [vec![3, 2], vec![1, 4], vec![5, 6]]);
+    }
+
+    #[test]
+    fn rounds_outside_the_schedule_have_no_conflicts() {
+        let schedule = Schedule::new(None, HashMap::from([(1, vec![(0, 50)])]));
+        let mut groups = vec![vec![1, 2], vec![3, 4]];
+        schedule.resolve_conflicts(&mut groups);
+        assert_eq!(groups, [vec![1, 2], vec![3, 4]]);
+    }
+}
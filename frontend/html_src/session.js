@@ -0,0 +1,53 @@
+// Warns shortly before the session runs out because of inactivity.
+// The timeout and the warning time in seconds are given as data attributes on the script tag.
+(function() {
+    const script = document.currentScript;
+    const timeout = Number(script.dataset.timeout) * 1000;
+    const warning = Number(script.dataset.warning) * 1000;
+    let expires = Date.now() + timeout;
+
+    const banner = document.createElement("div");
+    banner.style.cssText = "position: fixed; top: 0; left: 0; right: 0; padding: 8px; text-align: center; background-color: #ffd24d; display: none; z-index: 10;";
+    const text = document.createElement("text");
+    const button = document.createElement("button");
+    button.textContent = "Stay logged in";
+    button.style.marginLeft = "10px";
+    button.onclick = () => fetch("/keepalive", {
+        method: "POST",
+        headers: { "X-CSRF-Token": document.querySelector("meta[name=csrf-token]").content },
+    }).then(response => {
+        // Without a session the server redirects to the login, which fetch follows.
+        if (response.ok && !response.redirected) {
+            banner.style.display = "none";
+        } else {
+            expired();
+        }
+    });
+    banner.append(text, button);
+    document.addEventListener("DOMContentLoaded", () => document.body.append(banner));
+
+    // Every request to the server, such as saving a draft, keeps the session alive.
+    const fetch_ = window.fetch;
+    window.fetch = function(...args) {
+        expires = Date.now() + timeout;
+        return fetch_.apply(this, args);
+    };
+
+    function expired() {
+        text.textContent = "Your session has expired. Reload the page to log in again.";
+        button.style.display = "none";
+        banner.style.display = "block";
+    }
+
+    setInterval(() => {
+        const left = expires - Date.now();
+        if (left <= 0) {
+            expired();
+        } else if (left <= warning) {
+            const minutes = Math.ceil(left / 60000);
+            text.textContent = `Your session expires in ${minutes} minute${minutes == 1 ? "" : "s"} because of inactivity.`;
+            button.style.display = "";
+            banner.style.display = "block";
+        }
+    }, 5000);
+})();
@@ -1,60 +1,73 @@
 use std::{panic::set_hook, sync::{Arc, Mutex}, collections::{HashSet, HashMap}};
 
-use common::{Competitors, PdfRequest, from_base_64, to_base_64};
+use common::{Competitors, Constraints, PdfRequest, Schedule, apply_schedule_and_constraints, from_base_64, make_groups, to_base_64};
 use js_sys::Error;
 
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::spawn_local;
-use web_sys::{console::log_1, window, Event, Document, Element, HtmlInputElement, HtmlTableElement, HtmlTableRowElement, HtmlElement};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{console::log_1, window, DragEvent, Event, Document, Element, HtmlInputElement, KeyboardEvent, HtmlTableElement, HtmlTableRowElement, HtmlElement, Headers, RequestInit, Response};
 
 #[wasm_bindgen]
 pub fn start(base_64: &str) {
     set_hook(Box::new(|p| log_1(&p.to_string().into())));
     let competitor_info: Competitors = from_base_64(base_64); 
-    let groups = make_groups(competitor_info.competitors,
+    let rankings = competitor_info.seeded.then_some(&competitor_info.rankings);
+    let competitors = competitor_info.competitors.clone();
+    let newcomers: HashSet<_> = competitor_info.newcomers.iter().copied().collect();
+    let mut groups = make_groups(competitor_info.competitors,
         competitor_info.delegates,
+        competitor_info.newcomers,
         competitor_info.stages,
-        competitor_info.stations);
+        competitor_info.stations,
+        rankings);
+    let schedule = Schedule::new(competitor_info.round_window, competitor_info.busy);
+    apply_schedule_and_constraints(&mut groups, &schedule, &competitor_info.constraints);
+    let has_draft = competitor_info.draft.is_some();
+    let automatic_groups = groups.clone();
+    if let Some(draft) = competitor_info.draft {
+        groups = restore_draft(draft, &competitors).unwrap_or(groups);
+    }
     let round_config = RoundConfig {
         competition: competitor_info.competition,
         stages: competitor_info.stages,
         stations: competitor_info.stations,
+        automatic_groups,
         groups,
         names: competitor_info.names,
         event: competitor_info.event,
         round: competitor_info.round,
 	seperate_stages: competitor_info.seperate_stages,
+        schedule,
+        constraints: competitor_info.constraints,
+        newcomers,
+        selected: HashSet::new(),
+        undo: Vec::new(),
+        redo: Vec::new(),
+        has_draft,
+        draft_revision: competitor_info.draft_revision,
+        draft_timer: None,
+        draft_error: None,
     };
     unsafe {
         ROUND_CONFIG = Some(Arc::new(Mutex::new(round_config)));
     }
+    let closure = Closure::<dyn Fn(Event)>::new(history_shortcut);
+    document().add_event_listener_with_callback("keydown", closure.into_js_value().unchecked_ref()).unwrap();
     redraw_round_config().unwrap();
 }
 
-fn make_groups(competitors: Vec<u64>, delegates: Vec<u64>, stages: u64, stations: u64) -> Vec<Vec<u64>> {
-    let capacity = stages * stations;
-    let no_of_groups = (competitors.len() as u64 - 1 + capacity) / capacity;
-    let map: HashSet<_> = delegates.into_iter().collect();
-    let mut competing_delegates: Vec<_> = competitors.iter().filter(|id| map.contains(&id)).cloned().collect();  
-    let mut competing_non_delegates: Vec<_> = competitors.iter().filter(|id| !map.contains(&id)).cloned().collect(); 
-    let delegate_distribution = distribution(competing_delegates.len() as u64, no_of_groups);
-    let competitor_distribution = distribution(competitors.len() as u64, no_of_groups);
-    (0..no_of_groups).map(|idx| {
-            let no_of_delegates = delegate_distribution[idx as usize];
-            let no_of_non_delegates = competitor_distribution[idx as usize] - no_of_delegates;
-            competing_non_delegates.split_off(competing_non_delegates.len() - no_of_non_delegates as usize)
-                .into_iter()
-                .chain(competing_delegates.split_off(competing_delegates.len() - no_of_delegates as usize))
-                .collect()
-        }).collect()
-}
-
-fn distribution(mut remaining: u64, no_of_groups: u64) -> Vec<u64> {
-    (0..no_of_groups).map(|group| {
-            let per_group = remaining / (no_of_groups - group);
-            remaining -= per_group;
-            per_group
-        }).collect()
+/// Adapts a saved draft to the current competitors of the round. Competitors who are no longer in the round are removed and
+/// new competitors are added to the smallest group.
+fn restore_draft(mut draft: Vec<Vec<u64>>, competitors: &[u64]) -> Option<Vec<Vec<u64>>> {
+    let current: HashSet<_> = competitors.iter().copied().collect();
+    for group in &mut draft {
+        group.retain(|id| current.contains(id));
+    }
+    let drafted: HashSet<_> = draft.iter().flatten().copied().collect();
+    for id in competitors.iter().filter(|id| !drafted.contains(id)) {
+        draft.iter_mut().min_by_key(|group| group.len())?.push(*id);
+    }
+    Some(draft)
 }
 
 #[derive(Clone)]
@@ -67,25 +80,232 @@ struct RoundConfig {
     event: String,
     round: u64,
     seperate_stages: bool,
+    schedule: Schedule,
+    constraints: Constraints,
+    newcomers: HashSet<u64>,
+    /// Competitors selected to be moved together.
+    selected: HashSet<u64>,
+    /// Groups as created by `make_groups`, used to reset manual changes.
+    automatic_groups: Vec<Vec<u64>>,
+    undo: Vec<Vec<Vec<u64>>>,
+    redo: Vec<Vec<Vec<u64>>>,
+    /// Whether the groups are saved as a draft on the server.
+    has_draft: bool,
+    /// Counts the changes to the draft, so the server can ignore saves which arrive after a newer one.
+    draft_revision: u64,
+    /// Timeout of the pending draft save.
+    draft_timer: Option<i32>,
+    /// Why the draft of the latest revision could not be saved or discarded. The next change saves it again.
+    draft_error: Option<String>,
 }
 
-fn move_competitor(event: Event) {
-    let t = async move {
-        let target: Element = event.current_target()
-            .unwrap()
-            .unchecked_into();
-        let id = target.id();
-        let numbers: Vec<_> = id.split("/").map(|z| z.parse().unwrap()).collect();
-        let group = numbers[0] as _;
-        let number = numbers[1] as _;
-        let translation = numbers[2];
-        get_round_config().lock()
-            .unwrap()
-            .move_competitor(group, number, translation);
-        redraw_round_config().unwrap();
+fn select_competitor(event: Event) {
+    let target: Element = event.current_target()
+        .unwrap()
+        .unchecked_into();
+    let Some((group, number)) = parse_position(&target.id()) else {
+        return;
     };
-    spawn_local(t);
+    get_round_config().lock()
+        .unwrap()
+        .toggle_selected(group, number);
+    redraw_round_config().unwrap();
+}
+
+fn drag_competitor(event: Event) {
+    let target: Element = event.current_target()
+        .unwrap()
+        .unchecked_into();
+    let event: DragEvent = event.unchecked_into();
+    event.data_transfer()
+        .unwrap()
+        .set_data("text/plain", &target.id())
+        .unwrap();
+}
+
+fn drop_competitor(event: Event) {
+    event.prevent_default();
+    let target: Element = event.current_target()
+        .unwrap()
+        .unchecked_into();
+    let Some(target_group) = target.get_attribute("data-group").and_then(|group| group.parse().ok()) else {
+        return;
+    };
+    let event: DragEvent = event.unchecked_into();
+    // Text or files dragged in from outside the page are ignored.
+    let data = event.data_transfer().and_then(|data| data.get_data("text/plain").ok());
+    let Some((group, number)) = data.as_deref().and_then(parse_position) else {
+        return;
+    };
+    update(|round_config| round_config.move_dragged(group, number, target_group));
+}
+
+/// Undo on ctrl+z and redo on ctrl+y or ctrl+shift+z.
+fn history_shortcut(event: Event) {
+    let event: KeyboardEvent = event.unchecked_into();
+    if !event.ctrl_key() && !event.meta_key() {
+        return;
+    }
+    let key = event.key().to_lowercase();
+    let action: fn(&mut RoundConfig) -> bool = match key.as_str() {
+        "z" if event.shift_key() => RoundConfig::redo,
+        "z" => RoundConfig::undo,
+        "y" => RoundConfig::redo,
+        _ => return,
+    };
+    event.prevent_default();
+    update(action);
+}
+
+fn undo_on_click() {
+    update(RoundConfig::undo);
+}
+
+fn redo_on_click() {
+    update(RoundConfig::redo);
+}
+
+fn reset_on_click() {
+    update(RoundConfig::reset);
+}
+
+fn discard_draft_on_click() {
+    let (url, revision) = {
+        let rc = get_round_config();
+        let mut lock = rc.lock().unwrap();
+        lock.discard();
+        lock.has_draft = false;
+        if let Some(timer) = lock.draft_timer.take() {
+            window().unwrap().clear_timeout_with_handle(timer);
+        }
+        lock.draft_revision += 1;
+        (lock.draft_url(), lock.draft_revision)
+    };
+    send_draft("DELETE", url, None, revision);
+    redraw_round_config().unwrap();
+}
+
+/// Milliseconds without changes after which the draft is saved, so a burst of changes is saved once.
+const DRAFT_SAVE_DELAY: i32 = 1000;
+
+/// Applies a change to the groups. If anything changed the editor is redrawn and the draft on the server is updated
+/// once no more changes follow.
+fn update(change: impl FnOnce(&mut RoundConfig) -> bool) {
+    {
+        let rc = get_round_config();
+        let mut lock = rc.lock().unwrap();
+        if !change(&mut lock) {
+            return;
+        }
+        lock.has_draft = true;
+        lock.draft_revision += 1;
+        let window = window().unwrap();
+        if let Some(timer) = lock.draft_timer.take() {
+            window.clear_timeout_with_handle(timer);
+        }
+        let save = Closure::once_into_js(save_draft);
+        lock.draft_timer = window.set_timeout_with_callback_and_timeout_and_arguments_0(save.unchecked_ref(), DRAFT_SAVE_DELAY).ok();
+    }
+    redraw_round_config().unwrap();
+}
+
+fn save_draft() {
+    let (url, body, revision) = {
+        let rc = get_round_config();
+        let mut lock = rc.lock().unwrap();
+        lock.draft_timer = None;
+        (lock.draft_url(), to_base_64(&lock.groups), lock.draft_revision)
+    };
+    send_draft("POST", url, Some(body), revision);
+}
+
+/// Saves or discards the draft on the server and shows whether that failed. Only the outcome of the latest revision is
+/// shown, as the server replaces earlier revisions with it anyway.
+fn send_draft(method: &'static str, url: String, body: Option<String>, revision: u64) {
+    spawn_local(async move {
+        let result = send(method, &url, body.as_deref()).await;
+        {
+            let rc = get_round_config();
+            let mut lock = rc.lock().unwrap();
+            if lock.draft_revision != revision {
+                return;
+            }
+            lock.draft_error = match result {
+                Ok(()) => None,
+                Err(e) if body.is_some() => Some(format!("Your changes could not be saved: {e}")),
+                Err(e) => {
+                    // The draft is still on the server, so it can be discarded again.
+                    lock.has_draft = true;
+                    Some(format!("The draft could not be discarded: {e}"))
+                }
+            };
+        }
+        redraw_round_config().unwrap();
+    });
+}
 
+/// The csrf token the server put in the page. State changing requests are rejected without it.
+fn csrf_token() -> String {
+    document().query_selector("meta[name=csrf-token]")
+        .ok()
+        .flatten()
+        .and_then(|meta| meta.get_attribute("content"))
+        .unwrap_or_default()
+}
+
+/// Sends a state changing request. Fails with a message for the user if the request did not reach the server or the
+/// server did not carry it out.
+async fn send(method: &str, url: &str, body: Option<&str>) -> Result<(), String> {
+    let response = fetch(method, url, body).await
+        .map_err(|_| "The server could not be reached.".to_string())?;
+    // Page loads without a session are redirected to the login, which fetch follows without telling.
+    if response.redirected() {
+        return Err("Your session has expired. Reload the page to log in again.".to_string());
+    }
+    if response.status() == 401 {
+        // The server says why, such as having to log in to the WCA website again.
+        let message = match response.text() {
+            Ok(text) => JsFuture::from(text).await.ok().and_then(|text| text.as_string()),
+            Err(_) => None,
+        };
+        return Err(message.unwrap_or_else(|| "You are not logged in.".to_string()));
+    }
+    if !response.ok() {
+        return Err(format!("The server answered with status {}.", response.status()));
+    }
+    Ok(())
+}
+
+async fn fetch(method: &str, url: &str, body: Option<&str>) -> Result<Response, JsValue> {
+    let headers = Headers::new()?;
+    headers.set("X-CSRF-Token", &csrf_token())?;
+    let mut init = RequestInit::new();
+    init.method(method);
+    init.headers(&headers);
+    if let Some(body) = body {
+        init.body(Some(&JsValue::from_str(body)));
+    }
+    let response = JsFuture::from(window().unwrap().fetch_with_str_and_init(url, &init)).await?;
+    Ok(response.unchecked_into())
+}
+
+/// Group and position in the group from the id of a competitor's row, such as `1/4`.
+fn parse_position(id: &str) -> Option<(usize, usize)> {
+    let (group, number) = id.split_once('/')?;
+    Some((group.parse().ok()?, number.parse().ok()?))
+}
+
+fn add_listener(element: &Element, event: &str, listener: fn(Event)) -> Result<(), Error> {
+    let closure = Closure::<dyn Fn(Event)>::new(listener);
+    element.add_event_listener_with_callback(event, closure.into_js_value().unchecked_ref())?;
+    Ok(())
+}
+
+/// Makes a table cell accept competitors dropped onto it.
+fn make_drop_target(cell: &Element, group: usize) -> Result<(), Error> {
+    cell.set_attribute("data-group", &group.to_string())?;
+    add_listener(cell, "dragover", |event| event.prevent_default())?;
+    add_listener(cell, "drop", drop_competitor)
 }
 
 fn redraw_round_config() -> Result<(), Error> {
@@ -94,53 +314,109 @@ fn redraw_round_config() -> Result<(), Error> {
     let lock = rc.lock().unwrap();
     let groups = &lock.groups;
     let names = &lock.names;
+    let conflicting = lock.schedule.conflicting(groups);
+    let violations = lock.constraints.violations(groups);
+    let violating: HashSet<_> = violations.iter().flat_map(|&(a, b)| [a, b]).collect();
     let no_of_rows = groups.iter().map(|group| group.len()).max().unwrap_or(0);
-    for _ in 0..no_of_rows {
-        table.insert_row()?;
+    let header: HtmlTableRowElement = table.insert_row()?.unchecked_into();
+    for (group, members) in groups.iter().enumerate() {
+        let cell = header.insert_cell()?;
+        let newcomers = members.iter().filter(|id| lock.newcomers.contains(id)).count();
+        cell.set_text_content(Some(&format!("Group {} ({}, {} newcomers)", group + 1, members.len(), newcomers)));
+        make_drop_target(&cell, group)?;
     }
-    let no_of_groups = groups.len();
-    let rows = table.rows();
     for number in 0..no_of_rows {
-        let item: HtmlTableRowElement = rows.item(number as u32).unwrap().unchecked_into();
-        for group in 0..no_of_groups {
-            let l_cell = item.insert_cell().unwrap();
-            let cell = item.insert_cell().unwrap();
-            let r_cell = item.insert_cell().unwrap();
-            match groups[group].get(number) {
-                Some(id) => {
-                    let left_button = document().create_element("button")?;
-                    left_button.set_text_content(Some("<"));
-                    left_button.set_id(&format!("{}/{}/{}", group, number, -1));
-                    let closure = Closure::once(move_competitor);
-                    left_button.add_event_listener_with_callback("click", closure.into_js_value().unchecked_ref())?;
-                    let right_button = document().create_element("button")?;
-                    right_button.set_text_content(Some(">"));
-                    right_button.set_id(&format!("{}/{}/{}", group, number, 1));
-                    let closure = Closure::once(move_competitor);
-                    right_button.add_event_listener_with_callback("click", closure.into_js_value().unchecked_ref())?;
-                    let text = document().create_element("text")?;
-                    text.set_text_content(Some(&format!("{} ({})", id, names[id])));
-                    if group != 0 {
-                        l_cell.append_child(&left_button)?;
-                    }
-                    cell.append_child(&text)?;
-                    if group != no_of_groups - 1 {
-                        r_cell.append_child(&right_button)?;
-                    }
-
-                },
-                None => (),
-            } 
+        let item: HtmlTableRowElement = table.insert_row()?.unchecked_into();
+        for (group, members) in groups.iter().enumerate() {
+            let cell = item.insert_cell()?;
+            make_drop_target(&cell, group)?;
+            if let Some(id) = members.get(number) {
+                let text = document().create_element("text")?;
+                text.set_text_content(Some(&format!("{} ({})", id, names[id])));
+                text.set_id(&format!("{}/{}", group, number));
+                text.set_attribute("draggable", "true")?;
+                add_listener(&text, "dragstart", drag_competitor)?;
+                add_listener(&text, "click", select_competitor)?;
+                let mut classes = Vec::new();
+                if conflicting.contains(id) || violating.contains(id) {
+                    classes.push("conflict");
+                }
+                if lock.selected.contains(id) {
+                    classes.push("selected");
+                }
+                text.set_class_name(&classes.join(" "));
+                cell.append_child(&text)?;
+                if lock.newcomers.contains(id) {
+                    let badge = document().create_element("span")?;
+                    badge.set_class_name("newcomer");
+                    badge.set_text_content(Some("NEW"));
+                    cell.append_child(&badge)?;
+                }
+            }
         }
     }
     remove_all_children("main");
     let main = document().get_element_by_id("main")
         .unwrap();
+    if !conflicting.is_empty() {
+        let warning = document().create_element("h3")?;
+        warning.set_class_name("error_field");
+        warning.set_text_content(Some(&format!("Warning: {} competitors are in groups that overlap their other assignments.", conflicting.len())));
+        main.append_child(&warning)?;
+    }
+    if let Some(error) = &lock.draft_error {
+        let warning = document().create_element("h3")?;
+        warning.set_class_name("error_field");
+        warning.set_text_content(Some(error));
+        main.append_child(&warning)?;
+    }
+    for (a, b) in &violations {
+        let warning = document().create_element("h3")?;
+        warning.set_class_name("error_field");
+        warning.set_text_content(Some(&format!("Warning: The constraint between {} and {} is not satisfied.", names[a], names[b])));
+        main.append_child(&warning)?;
+    }
+    let history = document().create_element("div")?;
+    let undo = lock.undo.is_empty();
+    let redo = lock.redo.is_empty();
+    let reset = lock.groups == lock.automatic_groups;
+    let mut buttons: Vec<(&str, fn(), bool)> = vec![
+        ("Undo", undo_on_click, undo),
+        ("Redo", redo_on_click, redo),
+        ("Reset to automatic grouping", reset_on_click, reset),
+    ];
+    if lock.has_draft {
+        buttons.push(("Discard draft", discard_draft_on_click, false));
+    }
+    for (text, on_click, disabled) in buttons {
+        let button = document().create_element("button")?;
+        button.set_text_content(Some(text));
+        if disabled {
+            button.set_attribute("disabled", "")?;
+        }
+        let closure = Closure::once(on_click);
+        button.add_event_listener_with_callback("click", closure.into_js_value().unchecked_ref())?;
+        history.append_child(&button)?;
+    }
+    main.append_child(&history)?;
     main.append_child(&table)?;
+    let export = document().create_element("div")?;
+    let txt = document().create_element("text")?;
+    txt.set_text_content(Some("Export these groups: "));
+    export.append_child(&txt)?;
+    let buttons: [(&str, fn()); 2] = [("CSV", export_csv_on_click), ("Spreadsheet", export_xlsx_on_click)];
+    for (text, on_click) in buttons {
+        let button = document().create_element("button")?;
+        button.set_text_content(Some(text));
+        let closure = Closure::once(on_click);
+        button.add_event_listener_with_callback("click", closure.into_js_value().unchecked_ref())?;
+        export.append_child(&button)?;
+    }
+    main.append_child(&export)?;
     let submit = document().create_element("button")?;
     submit.set_text_content(Some("Submit!"));
     let closure = Closure::once(submit_on_click);
-    submit.add_event_listener_with_callback("click", &closure.into_js_value().unchecked_ref())?;
+    submit.add_event_listener_with_callback("click", closure.into_js_value().unchecked_ref())?;
     let document = document();
     let input: HtmlInputElement = document.create_element("input")?.dyn_into().unwrap();
     input.set_id("checkbox");
@@ -151,6 +427,15 @@ fn redraw_round_config() -> Result<(), Error> {
     div.append_child(&txt)?;
     div.append_child(&input)?;
     main.append_child(&div)?;
+    let staff: HtmlInputElement = document.create_element("input")?.dyn_into().unwrap();
+    staff.set_id("staff");
+    staff.set_type("checkbox");
+    let div = document.create_element("div")?;
+    let txt = document.create_element("text")?;
+    txt.set_text_content(Some("Do you want to generate judge, scrambler and runner assignments? (Requires patching to wcif)"));
+    div.append_child(&txt)?;
+    div.append_child(&staff)?;
+    main.append_child(&div)?;
     main.append_child(&submit)?;
     Ok(())
 }
@@ -159,7 +444,7 @@ static mut ROUND_CONFIG: Option<Arc<Mutex<RoundConfig>>> = None;
 
 fn get_round_config() -> Arc<Mutex<RoundConfig>> { 
     unsafe {
-        ROUND_CONFIG.clone().unwrap()
+        (*std::ptr::addr_of!(ROUND_CONFIG)).clone().unwrap()
     }
 }
 
@@ -168,8 +453,19 @@ fn submit_on_click() {
         let checkbox: HtmlInputElement = document().get_element_by_id("checkbox")
             .unwrap()
             .unchecked_into();
+        let staff: HtmlInputElement = document().get_element_by_id("staff")
+            .unwrap()
+            .unchecked_into();
         let rc = get_round_config();
         let round_config = rc.lock().unwrap();
+        let conflicts = round_config.schedule.conflicting(&round_config.groups).len();
+        let violations = round_config.constraints.violations(&round_config.groups).len();
+        if conflicts > 0 || violations > 0 {
+            let message = format!("{conflicts} competitors have schedule conflicts and {violations} grouping constraints are not satisfied. Submit anyway?");
+            if !window().unwrap().confirm_with_message(&message).unwrap() {
+                return;
+            }
+        }
         let pdf_request = PdfRequest {
             competition: round_config.competition.clone(),
             stages: round_config.stages,
@@ -179,25 +475,156 @@ fn submit_on_click() {
             event: round_config.event.clone(),
             round: round_config.round,
 	    seperate_stages: round_config.seperate_stages,
+            staff: staff.checked(),
         };
         let base64 = to_base_64(&pdf_request);
-        let url = format!("/pdf?data={base64}&wtf");
-        let element: HtmlElement = document().create_element("a").unwrap().unchecked_into();
-        element.set_attribute("href", &url).unwrap();
-        document().get_element_by_id("main")
-            .unwrap()
-            .append_child(&element).unwrap();
-        element.click();
+        // Patching shows what changes in the wcif first and only patches after confirming.
+        let url = if pdf_request.wcif {
+            format!("/pdf/preview?data={base64}")
+        } else {
+            format!("/pdf?data={base64}&wtf")
+        };
+        open(&url);
     };
     spawn_local(t);
 }
 
+fn export_csv_on_click() {
+    export("csv");
+}
+
+fn export_xlsx_on_click() {
+    export("xlsx");
+}
+
+/// Downloads the current groups, which do not have to be submitted or saved for this.
+fn export(format: &str) {
+    let url = {
+        let rc = get_round_config();
+        let round_config = rc.lock().unwrap();
+        let pdf_request = PdfRequest {
+            competition: round_config.competition.clone(),
+            stages: round_config.stages,
+            stations: round_config.stations,
+            groups: round_config.groups.clone(),
+            wcif: false,
+            event: round_config.event.clone(),
+            round: round_config.round,
+            seperate_stages: round_config.seperate_stages,
+            staff: false,
+        };
+        format!("/export?data={}&format={format}", to_base_64(&pdf_request))
+    };
+    open(&url);
+    // The buttons only handle one click, so they are drawn again to allow another export.
+    redraw_round_config().unwrap();
+}
+
+fn open(url: &str) {
+    let element: HtmlElement = document().create_element("a").unwrap().unchecked_into();
+    element.set_attribute("href", url).unwrap();
+    document().get_element_by_id("main")
+        .unwrap()
+        .append_child(&element).unwrap();
+    element.click();
+}
+
 
 
 impl RoundConfig {
-    fn move_competitor(&mut self, group: usize, number: usize, translation: isize) {
+    fn move_competitor(&mut self, group: usize, number: usize, target: usize) {
+        if group == target {
+            return;
+        }
         let id = self.groups[group].remove(number);
-        self.groups[(group as isize + translation) as usize].push(id);
+        self.groups[target].push(id);
+    }
+
+    /// Moves a dragged competitor to the target group. If the competitor is selected the whole selection is moved.
+    fn move_dragged(&mut self, group: usize, number: usize, target: usize) -> bool {
+        let Some(&id) = self.groups.get(group).and_then(|members| members.get(number)) else {
+            return false;
+        };
+        if target >= self.groups.len() {
+            return false;
+        }
+        let moved = if self.selected.contains(&id) { self.selected.iter().copied().collect() } else { vec![id] };
+        // Dropping into the group the competitors are already in changes nothing, so there is nothing to undo.
+        if moved.iter().all(|id| self.position(*id).is_none_or(|(group, _)| group == target)) {
+            return false;
+        }
+        self.record();
+        if !self.selected.contains(&id) {
+            self.move_competitor(group, number, target);
+            return true;
+        }
+        for id in std::mem::take(&mut self.selected) {
+            if let Some((group, number)) = self.position(id) {
+                self.move_competitor(group, number, target);
+            }
+        }
+        true
+    }
+
+    /// Saves the current groups so the next change can be undone.
+    fn record(&mut self) {
+        self.undo.push(self.groups.clone());
+        self.redo.clear();
+    }
+
+    fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some(groups) => {
+                self.redo.push(std::mem::replace(&mut self.groups, groups));
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(groups) => {
+                self.undo.push(std::mem::replace(&mut self.groups, groups));
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn reset(&mut self) -> bool {
+        if self.groups == self.automatic_groups {
+            return false;
+        }
+        self.record();
+        self.groups = self.automatic_groups.clone();
+        self.selected.clear();
+        true
+    }
+
+    /// Goes back to the automatic groups and forgets the history, so a discarded draft can not come back by undoing.
+    fn discard(&mut self) {
+        self.groups = self.automatic_groups.clone();
+        self.selected.clear();
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    fn draft_url(&self) -> String {
+        format!("/{}/{}/{}/draft?revision={}", self.competition, self.event, self.round, self.draft_revision)
+    }
+
+    fn toggle_selected(&mut self, group: usize, number: usize) {
+        let id = self.groups[group][number];
+        if !self.selected.remove(&id) {
+            self.selected.insert(id);
+        }
+    }
+
+    fn position(&self, id: u64) -> Option<(usize, usize)> {
+        self.groups.iter()
+            .enumerate()
+            .find_map(|(group, members)| Some((group, members.iter().position(|&other| other == id)?)))
     }
 
     fn submit(&self) -> Result<&Vec<Vec<u64>>, String> {